@@ -0,0 +1,269 @@
+// crud.rs
+// benchmarks the SQL behind the create, read and search routes against an in-memory database
+// migrated with the server's own migrations, at 100, 10,000 and 100,000 records
+// the statements come from src/queries.rs, which the handlers build theirs from too, so a change
+// to a query, an index or the schema shows up here, only the SQL is timed, not the rest of the
+// request, and a create is just its insert, without the audit and outbox rows the route adds
+// each is timed over a fixed number of runs after a warm-up and reported as the mean latency and
+// the runs per second, every size starts from a freshly seeded database and creates are removed
+// again between runs, outside the timing, so each runs against a table of exactly that size
+// the index comparisons run the same lookup with its FROM test made NOT INDEXED, and the paged
+// reads are timed with /database_read's OFFSET paging next to /database_changes' keyset paging
+// run with `cargo bench`, or `cargo bench -- 10000` for the sizes that contain "10000"
+
+// the crate is a binary, so the shared queries are included from their source file, the bench
+// doesn't run every statement in it
+#[allow(dead_code)]
+#[path = "../src/queries.rs"]
+mod queries;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::future::Future;
+use std::hint::black_box;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// table sizes benchmarked
+const SIZES: &[i64] = &[100, 10_000, 100_000];
+
+// runs before timing starts, and runs timed
+const WARM_UP_RUNS: u32 = 20;
+const TIMED_RUNS: u32 = 500;
+
+// page size of the paged reads, the default page size of the read route
+const PAGE_SIZE: i64 = 50;
+
+// distinct dates the records are spread over, so a date matches about 1/DATES of the table
+const DATES: i64 = 365;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // cargo bench passes --bench, anything else is taken as a filter on the size
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+
+    for &size in SIZES {
+        if filter
+            .as_deref()
+            .is_some_and(|filter| !size.to_string().contains(filter))
+        {
+            continue;
+        }
+        let pool = seeded_pool(size).await;
+        println!("\n{} records", size);
+        bench_reads(&pool, size).await;
+        bench_searches(&pool).await;
+        bench_create(&pool, size).await;
+        pool.close().await;
+    }
+}
+
+// a migrated in-memory database holding size records
+// an in-memory database belongs to its connection, so the pool keeps exactly one open
+async fn seeded_pool(size: i64) -> SqlitePool {
+    let options = SqliteConnectOptions::from_str("sqlite::memory:").expect("a valid database URL");
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .expect("an in-memory database opens");
+    sqlx::migrate!("./migrations")
+        .run(&pool)
+        .await
+        .expect("the migrations apply");
+
+    let mut tx = pool.begin().await.expect("a transaction starts");
+    for id in 1..=size {
+        sqlx::query(
+            "INSERT INTO test (id, date, message, updated_at) \
+             VALUES ($1, date('2023-01-01', $2), $3, \
+             strftime('%Y-%m-%dT%H:%M:%fZ', '2026-01-01', $4))",
+        )
+        .bind(id)
+        .bind(format!("+{} days", id % DATES))
+        .bind(format!("message number {}", id))
+        .bind(format!("+{} seconds", id))
+        .execute(&mut *tx)
+        .await
+        .expect("a record inserts");
+    }
+    tx.commit().await.expect("the records commit");
+    pool
+}
+
+// the single-record read and the paged read, first and last page
+async fn bench_reads(pool: &SqlitePool, size: i64) {
+    let mut id = 0;
+    measure("read one by id (/database/{id})", || {
+        id = id % size + 1;
+        async move {
+            sqlx::query(queries::RECORD_BY_ID)
+                .bind(id)
+                .fetch_optional(pool)
+                .await
+                .expect("the read runs")
+        }
+    })
+    .await;
+
+    // records were seeded a second apart in id order, so the keyset page after the record with
+    // id offset holds the same records as the OFFSET page
+    let page_sql = queries::page("", 1);
+    let page_sql = page_sql.as_str();
+    let last_page = (size - PAGE_SIZE).max(0);
+    for (label, offset) in [("first", 0), ("last", last_page)] {
+        // the first page starts before any record, like a read without since
+        let since: String = sqlx::query_scalar("SELECT updated_at FROM test WHERE id = $1")
+            .bind(offset)
+            .fetch_optional(pool)
+            .await
+            .expect("the record reads")
+            .unwrap_or_default();
+        let since = since.as_str();
+        measure(
+            &format!("read {} page, OFFSET (/database_read)", label),
+            || async move {
+                sqlx::query(page_sql)
+                    .bind(PAGE_SIZE)
+                    .bind(offset)
+                    .fetch_all(pool)
+                    .await
+                    .expect("the read runs")
+            },
+        )
+        .await;
+        measure(
+            &format!("read {} page, keyset (/database_changes)", label),
+            || async move {
+                sqlx::query(queries::CHANGES)
+                    .bind(since)
+                    .bind(offset)
+                    .bind(PAGE_SIZE)
+                    .fetch_all(pool)
+                    .await
+                    .expect("the read runs")
+            },
+        )
+        .await;
+    }
+}
+
+// the date and message lookups, with and without their indexes
+async fn bench_searches(pool: &SqlitePool) {
+    // the where clause Filter builds for ?filter=date=...
+    let count_sql = queries::count(" WHERE date = $1");
+    for (label, sql) in [
+        ("count one date (?filter=date=), indexed", count_sql.clone()),
+        (
+            "count one date (?filter=date=), NOT INDEXED",
+            not_indexed(&count_sql),
+        ),
+    ] {
+        let sql = sql.as_str();
+        measure(label, || async move {
+            sqlx::query_scalar::<_, i64>(sql)
+                .bind("2023-06-01")
+                .fetch_one(pool)
+                .await
+                .expect("the count runs")
+        })
+        .await;
+    }
+
+    for (label, sql) in [
+        (
+            "message prefix (/database_autocomplete), indexed",
+            queries::AUTOCOMPLETE.to_string(),
+        ),
+        (
+            "message prefix (/database_autocomplete), NOT INDEXED",
+            not_indexed(queries::AUTOCOMPLETE),
+        ),
+    ] {
+        let sql = sql.as_str();
+        measure(label, || async move {
+            sqlx::query_scalar::<_, String>(sql)
+                .bind("message number 99%")
+                .bind(10)
+                .fetch_all(pool)
+                .await
+                .expect("the search runs")
+        })
+        .await;
+    }
+}
+
+// creates, each in its own transaction as /database_create runs them, the record is deleted
+// again after each run so the table stays at size records
+async fn bench_create(pool: &SqlitePool, size: i64) {
+    let id = size + 1;
+    measure_with_reset(
+        "create one (/database_create)",
+        || async move {
+            let mut tx = pool.begin().await.expect("a transaction starts");
+            sqlx::query(queries::INSERT_RECORD)
+                .bind(id)
+                .bind("2026-10-16")
+                .bind("a benchmarked message")
+                .bind(None::<Vec<u8>>)
+                .bind("2026-10-16T00:00:00.000Z")
+                .execute(&mut *tx)
+                .await
+                .expect("the create runs");
+            tx.commit().await.expect("the create commits");
+        },
+        || async move {
+            sqlx::query("DELETE FROM test WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await
+                .expect("the created record deletes");
+        },
+    )
+    .await;
+}
+
+// the statement with its table lookup made NOT INDEXED, so SQLite scans the table instead
+fn not_indexed(sql: &str) -> String {
+    assert!(sql.contains("FROM test "), "the statement reads test");
+    sql.replacen("FROM test ", "FROM test NOT INDEXED ", 1)
+}
+
+// time a benchmark and print its mean latency and throughput
+async fn measure<F, Fut, T>(name: &str, run: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+{
+    measure_with_reset(name, run, || async {}).await;
+}
+
+// time a benchmark that changes the database, reset puts it back after each run and isn't timed
+async fn measure_with_reset<F, Fut, T, R, RFut>(name: &str, mut run: F, mut reset: R)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = T>,
+    R: FnMut() -> RFut,
+    RFut: Future<Output = ()>,
+{
+    for _ in 0..WARM_UP_RUNS {
+        black_box(run().await);
+        reset().await;
+    }
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..TIMED_RUNS {
+        let started = Instant::now();
+        black_box(run().await);
+        elapsed += started.elapsed();
+        reset().await;
+    }
+    let mean = elapsed / TIMED_RUNS;
+    println!(
+        "  {:<56} {:>10.1?} {:>10.0}/s",
+        name,
+        mean,
+        1.0 / mean.as_secs_f64()
+    );
+}
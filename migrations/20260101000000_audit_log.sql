@@ -0,0 +1,12 @@
+-- audit log of every mutation to the test table, with a full before-image of the row
+
+CREATE TABLE audit_log(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  record_id INTEGER NOT NULL,
+  operation TEXT NOT NULL,
+  before_image TEXT,
+  changed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  undone INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX audit_log_record_id ON audit_log(record_id);
@@ -0,0 +1,16 @@
+-- outbox of webhook events, written in the same transaction as the change it describes and
+-- delivered by a background worker, so an event survives a crash between commit and delivery
+-- status is 'pending' until delivered ('sent') or out of attempts ('dead')
+
+CREATE TABLE outbox(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  event TEXT NOT NULL,
+  payload TEXT NOT NULL,
+  status TEXT NOT NULL DEFAULT 'pending',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+  last_error TEXT,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX outbox_pending ON outbox(status, next_attempt_at);
@@ -0,0 +1,7 @@
+-- a second table, reachable through the generic /db/{table} routes alongside test
+
+CREATE TABLE notes(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  title TEXT NOT NULL,
+  body TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- zstd compressed copy of long messages, written when COMPRESS_MESSAGES is on, the message
+-- column of such a row is left empty, rows written before or without compression keep their
+-- plain text and a NULL here
+
+ALTER TABLE test ADD COLUMN message_zstd BLOB;
+
+-- a plain text message written over a compressed one (e.g. through the generic /db routes)
+-- replaces it, so the two columns never disagree
+CREATE TRIGGER test_message_plain AFTER UPDATE OF message ON test
+WHEN NEW.message <> '' AND NEW.message_zstd IS NOT NULL
+BEGIN
+  UPDATE test SET message_zstd = NULL WHERE id = NEW.id;
+END;
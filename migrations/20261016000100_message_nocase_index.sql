@@ -0,0 +1,4 @@
+-- lets the prefix match of /database_autocomplete (message LIKE 'he%') use an index, SQLite's
+-- LIKE is case-insensitive so the index has to be too
+
+CREATE INDEX test_message_nocase ON test(message COLLATE NOCASE);
@@ -0,0 +1,4 @@
+-- serves /database_by_date, /database_dates and date conditions in ?filter=, which would
+-- otherwise scan the whole table
+
+CREATE INDEX test_date ON test(date);
@@ -0,0 +1,8 @@
+-- ids reserved with /database_reserve, a reserved row is a placeholder with an empty message
+-- until /database_confirm/{id} fills it in and clears reserved_until, an RFC 3339 UTC time
+-- after which the reservation lapses and the row is swept, confirmed records keep a NULL here
+
+ALTER TABLE test ADD COLUMN reserved_until TEXT;
+
+-- the sweeper only looks at reserved rows
+CREATE INDEX test_reserved_until ON test(reserved_until) WHERE reserved_until IS NOT NULL;
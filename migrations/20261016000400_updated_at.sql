@@ -0,0 +1,20 @@
+-- server-managed time each record was last written, an RFC 3339 UTC time with milliseconds so
+-- it sorts and compares as text, kept up to date by triggers rather than by every statement
+-- that writes the table, existing records are stamped with the time of the migration
+
+ALTER TABLE test ADD COLUMN updated_at TEXT;
+
+UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+
+CREATE TRIGGER test_updated_at_insert AFTER INSERT ON test
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
+
+-- listing the columns keeps the trigger from firing on its own update of updated_at
+CREATE TRIGGER test_updated_at_update AFTER UPDATE OF date, message, message_zstd, reserved_until ON test
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
+
+CREATE INDEX test_updated_at ON test(updated_at, id);
@@ -0,0 +1,20 @@
+-- the routes that write records now set updated_at themselves, in the same statement as the
+-- rest of the row, so RETURNING sees the new value, which it can't when an AFTER trigger writes
+-- it, the triggers stay as a backstop for statements that leave updated_at alone, such as the
+-- generic table routes and imports, and only fire when the statement didn't set it
+
+DROP TRIGGER test_updated_at_insert;
+DROP TRIGGER test_updated_at_update;
+
+CREATE TRIGGER test_updated_at_insert AFTER INSERT ON test
+WHEN NEW.updated_at IS NULL
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
+
+-- listing the columns keeps the trigger from firing on its own update of updated_at
+CREATE TRIGGER test_updated_at_update AFTER UPDATE OF date, message, message_zstd, reserved_until ON test
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
@@ -0,0 +1,13 @@
+-- records are now read with a typed YYYY-MM-DD date, so dates written before the API checked
+-- them are rewritten into that form where SQLite can read them (2023-01-25 10:00 becomes
+-- 2023-01-25), the before-images in the audit log are rewritten the same way so they can still
+-- be undone, a date SQLite can't read is left alone and reported when its record is read
+
+UPDATE test SET date = date(date)
+WHERE date(date) IS NOT NULL AND date(date) != date;
+
+UPDATE audit_log
+SET before_image = json_set(before_image, '$.date', date(json_extract(before_image, '$.date')))
+WHERE before_image IS NOT NULL
+  AND date(json_extract(before_image, '$.date')) IS NOT NULL
+  AND date(json_extract(before_image, '$.date')) != json_extract(before_image, '$.date');
@@ -0,0 +1,56 @@
+-- server-managed time each record was first stored, in the same form as updated_at, and never
+-- changed by a later write, the routes that insert records set it in the same statement as
+-- updated_at so RETURNING sees it, the column default covers the generic table routes and imports
+-- SQLite can't add a column with a non-constant default, so the table is rebuilt, its indexes and
+-- triggers recreated as the earlier migrations left them and its AUTOINCREMENT counter carried
+-- over, existing records get the time of their earliest audited create, or failing that their
+-- updated_at, which is the best that's known of them
+
+CREATE TABLE test_new(
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  date TEXT NOT NULL,
+  message TEXT NOT NULL,
+  message_zstd BLOB,
+  reserved_until TEXT,
+  updated_at TEXT,
+  created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+INSERT INTO test_new (id, date, message, message_zstd, reserved_until, updated_at, created_at)
+SELECT id, date, message, message_zstd, reserved_until, updated_at,
+       COALESCE(
+         (SELECT strftime('%Y-%m-%dT%H:%M:%fZ', MIN(changed_at)) FROM audit_log
+          WHERE audit_log.record_id = test.id AND operation = 'create'),
+         updated_at,
+         strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+FROM test;
+
+DELETE FROM sqlite_sequence WHERE name = 'test_new';
+INSERT INTO sqlite_sequence (name, seq) SELECT 'test_new', seq FROM sqlite_sequence WHERE name = 'test';
+
+DROP TABLE test;
+ALTER TABLE test_new RENAME TO test;
+
+CREATE INDEX test_message_nocase ON test(message COLLATE NOCASE);
+CREATE INDEX test_date ON test(date);
+CREATE INDEX test_reserved_until ON test(reserved_until) WHERE reserved_until IS NOT NULL;
+CREATE INDEX test_updated_at ON test(updated_at, id);
+
+CREATE TRIGGER test_message_plain AFTER UPDATE OF message ON test
+WHEN NEW.message <> '' AND NEW.message_zstd IS NOT NULL
+BEGIN
+  UPDATE test SET message_zstd = NULL WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER test_updated_at_insert AFTER INSERT ON test
+WHEN NEW.updated_at IS NULL
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
+
+-- listing the columns keeps the trigger from firing on its own update of updated_at
+CREATE TRIGGER test_updated_at_update AFTER UPDATE OF date, message, message_zstd, reserved_until ON test
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+  UPDATE test SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') WHERE id = NEW.id;
+END;
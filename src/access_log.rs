@@ -0,0 +1,77 @@
+// access_log.rs
+// classic one-line-per-request access log, enabled with ACCESS_LOG=combined
+// lines are written in Apache combined log format to the "access_log" tracing target, so they
+// can be told apart from the application's own output, e.g.
+// 127.0.0.1 - - [16/Oct/2026:10:01:02 +0000] "GET /database_read HTTP/1.1" 200 512 "-" "curl/8.5.0"
+
+use crate::allowlist;
+use crate::config::{AccessLog, Config};
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use std::sync::Arc;
+use tracing::info;
+
+// middleware function which writes an access log line once a request has been answered
+pub async fn log_request(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if config.access_log == AccessLog::Off {
+        return next.run(request).await;
+    }
+
+    let client = allowlist::client_ip(&config, &request)
+        .map_or_else(|| "-".to_string(), |ip| ip.to_string());
+    let request_line = format!(
+        "{} {} {:?}",
+        request.method(),
+        request.uri(),
+        request.version()
+    );
+    let referer = quoted(request.headers(), header::REFERER);
+    let user_agent = quoted(request.headers(), header::USER_AGENT);
+    let timestamp = Utc::now().format("%d/%b/%Y:%H:%M:%S %z");
+
+    let response = next.run(request).await;
+
+    // streamed bodies have no known size, like an empty one they are logged as "-"
+    let bytes = response
+        .body()
+        .size_hint()
+        .exact()
+        .filter(|bytes| *bytes > 0)
+        .map_or_else(|| "-".to_string(), |bytes| bytes.to_string());
+    info!(
+        target: "access_log",
+        "{} - - [{}] \"{}\" {} {} {} {}",
+        client,
+        timestamp,
+        escape(&request_line),
+        response.status().as_u16(),
+        bytes,
+        referer,
+        user_agent
+    );
+
+    response
+}
+
+// a request header as a quoted log field, "-" when it's missing
+fn quoted(headers: &HeaderMap, name: HeaderName) -> String {
+    match headers.get(name).and_then(|value| value.to_str().ok()) {
+        Some(value) => format!("\"{}\"", escape(value)),
+        None => "\"-\"".to_string(),
+    }
+}
+
+// escape quotes and backslashes so a field can't break the line apart
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,54 @@
+// admin_page.rs
+// "/admin", a single page for managing records from a browser without a separate front-end,
+// it lists, creates, updates and deletes records by calling the JSON routes with fetch
+// the page itself is static and holds nothing secret, it asks for the API key in a login form,
+// checks it against a guarded route and sends it as x-api-key with every request it makes
+// its script and styles are served from their own URLs so the default Content-Security-Policy
+// can forbid inline ones
+
+use axum::{
+    http::{header, StatusCode},
+    response::{Html, IntoResponse},
+};
+use tracing::instrument;
+
+// the page and its assets, embedded in the binary
+const ADMIN_PAGE: &str = include_str!("../templates/admin.html");
+const ADMIN_SCRIPT: &str = include_str!("../templates/admin.js");
+const ADMIN_STYLE: &str = include_str!("../templates/admin.css");
+
+// handler function for the admin page, it's never cached so a new build's page is picked up
+#[instrument(skip_all, fields(route = "/admin", method = "GET"))]
+pub async fn admin_page() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CACHE_CONTROL, "no-store")],
+        Html(ADMIN_PAGE),
+    )
+}
+
+// handler function for the admin page's script
+#[instrument(skip_all, fields(route = "/admin/page.js", method = "GET"))]
+pub async fn admin_script() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/javascript"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        ADMIN_SCRIPT,
+    )
+}
+
+// handler function for the admin page's styles
+#[instrument(skip_all, fields(route = "/admin/page.css", method = "GET"))]
+pub async fn admin_style() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "text/css"),
+            (header::CACHE_CONTROL, "no-store"),
+        ],
+        ADMIN_STYLE,
+    )
+}
@@ -0,0 +1,66 @@
+// allowlist.rs
+// restricts access to clients whose IP falls inside one of the ALLOWED_IPS ranges
+// the client IP is the TCP peer address, or the right-most X-Forwarded-For entry when
+// TRUST_PROXY is set, health and readiness checks are always allowed so probes keep working
+
+use crate::config::Config;
+use crate::error::AppError;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tracing::warn;
+
+// routes that skip the allowlist
+const EXEMPT_PATHS: &[&str] = &["/health_check", "/readyz"];
+
+// middleware function which rejects clients outside the allowlist with a 403
+// an empty allowlist lets everyone through
+pub async fn check_client_ip(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if config.allowed_ips.is_empty() || EXEMPT_PATHS.contains(&request.uri().path()) {
+        return Ok(next.run(request).await);
+    }
+
+    match client_ip(&config, &request) {
+        Some(ip) if config.allowed_ips.iter().any(|net| net.contains(&ip)) => {
+            Ok(next.run(request).await)
+        }
+        ip => {
+            warn!(
+                "rejected request from client outside the allowlist: {:?}",
+                ip
+            );
+            Err(AppError::Forbidden(
+                "client address is not allowed".to_string(),
+            ))
+        }
+    }
+}
+
+// work out the client IP, None when it can't be determined (e.g. over a Unix socket)
+pub fn client_ip(config: &Config, request: &Request) -> Option<IpAddr> {
+    if config.trust_proxy {
+        // the right-most entry was added by our own proxy, so it can't be spoofed by the client
+        let forwarded = request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
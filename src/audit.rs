@@ -0,0 +1,62 @@
+// audit.rs
+// audit log of mutations to the test table, used to undo the most recent change to a record
+// each entry stores the operation and a JSON before-image of the row (none for a create)
+
+use crate::TestRecord;
+use sqlx::sqlite::SqliteConnection;
+use sqlx::types::Json;
+use sqlx::FromRow;
+
+// the kinds of mutation recorded in the audit log
+pub const CREATE: &str = "create";
+pub const UPDATE: &str = "update";
+pub const DELETE: &str = "delete";
+
+// struct to hold a single entry read back from the audit log
+#[derive(Debug, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub operation: String,
+    pub before_image: Option<Json<TestRecord>>,
+}
+
+// write an audit entry, meant to run on the same transaction as the mutation it describes
+pub async fn record_change(
+    conn: &mut SqliteConnection,
+    record_id: i32,
+    operation: &str,
+    before: Option<&TestRecord>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO audit_log (record_id, operation, before_image) VALUES ($1, $2, $3)")
+        .bind(record_id)
+        .bind(operation)
+        .bind(before.map(Json))
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+// find the most recent change to a record that hasn't been undone yet
+pub async fn latest_change(
+    conn: &mut SqliteConnection,
+    record_id: i32,
+) -> Result<Option<AuditEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AuditEntry>(
+        "SELECT id, operation, before_image FROM audit_log \
+         WHERE record_id = $1 AND undone = 0 ORDER BY id DESC LIMIT 1",
+    )
+    .bind(record_id)
+    .fetch_optional(conn)
+    .await
+}
+
+// flag an audit entry as undone so the next undo reaches further back
+pub async fn mark_undone(conn: &mut SqliteConnection, entry_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE audit_log SET undone = 1 WHERE id = $1")
+        .bind(entry_id)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
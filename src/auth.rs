@@ -0,0 +1,44 @@
+// auth.rs
+// API key guard for routes that modify data in bulk or expose admin operations
+// clients send the key in the "x-api-key" header, it must match API_KEY from the config
+
+use crate::config::Config;
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+// name of the header carrying the API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// middleware function which rejects requests without a valid API key
+// when no API_KEY is configured every request to a guarded route is rejected
+pub async fn require_api_key(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let provided = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match (config.api_key.as_deref(), provided) {
+        (Some(expected), Some(provided)) if constant_time_eq(expected, provided) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(AppError::Unauthorized),
+    }
+}
+
+// compare two keys without returning early on the first mismatched byte
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
@@ -0,0 +1,54 @@
+// chaos.rs
+// fault injection for testing how clients cope with a slow or failing server, development only
+// CHAOS_DELAY_MS holds every request back before it's handled and CHAOS_ERROR_RATE answers
+// that fraction of requests with a 500 instead of handling them, Config refuses either setting
+// unless APP_ENV=development and the middleware checks again, so production can't run with them
+
+use crate::config::{AppEnv, Config};
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+// true when either fault is configured, used to announce chaos mode
+pub fn is_active(config: &Config) -> bool {
+    config.chaos_delay_ms > 0 || config.chaos_error_rate > 0.0
+}
+
+// middleware function which delays and fails requests as configured
+pub async fn inject_faults(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if config.app_env != AppEnv::Development || !is_active(&config) {
+        return Ok(next.run(request).await);
+    }
+
+    if config.chaos_delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(config.chaos_delay_ms)).await;
+    }
+    if config.chaos_error_rate > 0.0 && random_fraction() < config.chaos_error_rate {
+        warn!(
+            "chaos mode failed {} {} on purpose",
+            request.method(),
+            request.uri().path()
+        );
+        return Err(AppError::ChaosFailure);
+    }
+    Ok(next.run(request).await)
+}
+
+// a number in [0, 1), each RandomState is freshly keyed so its hash of nothing is random
+// enough to pick the requests that fail, without a dependency on a random number crate
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,55 @@
+// charset.rs
+// declares the character set of text responses, every body this API writes is UTF-8 but
+// application/json and its relatives go out without saying so, which some clients read as
+// Latin-1 and show non-ASCII messages garbled, enabled with CONTENT_TYPE_CHARSET
+// runs outside the envelope so it sees the final Content-Type of every response
+
+use crate::config::Config;
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+// the parameter appended to the Content-Type
+const UTF_8: &str = "charset=utf-8";
+
+// media types which get the charset, besides any text/*
+const TEXT_MEDIA_TYPES: &[&str] = &[
+    "application/json",
+    "application/problem+json",
+    "application/x-ndjson",
+    "application/xml",
+];
+
+// middleware function which appends "; charset=utf-8" to text Content-Types lacking a charset
+pub async fn add_charset(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if !config.content_type_charset {
+        return response;
+    }
+
+    let Some(content_type) = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return response;
+    };
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    let is_text = media_type.starts_with("text/") || TEXT_MEDIA_TYPES.contains(&media_type);
+    if !is_text || content_type.to_ascii_lowercase().contains("charset=") {
+        return response;
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&format!("{}; {}", content_type, UTF_8)) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    response
+}
@@ -0,0 +1,61 @@
+// compress.rs
+// transparent zstd compression of long messages, enabled with COMPRESS_MESSAGES
+// a message of at least COMPRESS_MIN_BYTES is stored compressed in the message_zstd column with
+// an empty message column, shorter ones (and every row written before compression was turned
+// on) keep their plain text, reads take whichever column is filled so old and new rows mix
+// freely and the API only ever sees the text, compressed messages can't be matched in SQL
+
+use crate::config::Config;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Error, Row};
+
+// column holding the compressed message
+pub const COLUMN: &str = "message_zstd";
+
+// zstd level used for messages, the library default, a good balance for short text
+const LEVEL: i32 = 0;
+
+// struct to hold a message as it's written to the message and message_zstd columns
+pub struct StoredMessage {
+    pub text: String,
+    pub zstd: Option<Vec<u8>>,
+}
+
+// work out how a message is stored, compressed when it's long enough and compression helps
+pub fn store(config: &Config, message: &str) -> StoredMessage {
+    let plain = || StoredMessage {
+        text: message.to_string(),
+        zstd: None,
+    };
+    if !config.compress_messages || message.len() < config.compress_min_bytes {
+        return plain();
+    }
+    match zstd::encode_all(message.as_bytes(), LEVEL) {
+        Ok(zstd) if zstd.len() < message.len() => StoredMessage {
+            text: String::new(),
+            zstd: Some(zstd),
+        },
+        _ => plain(),
+    }
+}
+
+// read the message of a row, decompressing it when the plain column is empty and the row
+// holds a compressed copy, plain text always wins as it's what any newer write left behind
+// a query that didn't select message_zstd reads the plain column only
+pub fn load(row: &SqliteRow) -> Result<String, Error> {
+    let text: String = row.try_get("message")?;
+    if !text.is_empty() {
+        return Ok(text);
+    }
+    match row.try_get::<Option<Vec<u8>>, _>(COLUMN) {
+        Ok(Some(zstd)) => decompress(&zstd),
+        Ok(None) | Err(Error::ColumnNotFound(_)) => Ok(text),
+        Err(e) => Err(e),
+    }
+}
+
+// turn a compressed message back into its text
+fn decompress(zstd: &[u8]) -> Result<String, Error> {
+    let bytes = zstd::decode_all(zstd).map_err(|e| Error::Decode(Box::new(e)))?;
+    String::from_utf8(bytes).map_err(|e| Error::Decode(Box::new(e)))
+}
@@ -0,0 +1,123 @@
+// concurrency.rs
+// caps how many requests are handled at once, whatever the size of the database pool
+// a request over the limit waits for a slot for up to REQUEST_TIMEOUT_SECS, the time it would
+// have had to run, and is then shed with a 503, which bounds the memory and CPU a traffic spike
+// can use while letting a short burst through
+
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+use tracing::warn;
+
+// struct to hold the slots shared by every request and how long a request waits for one,
+// sized from MAX_CONCURRENT_REQUESTS and REQUEST_TIMEOUT_SECS at startup
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit {
+    // None when MAX_CONCURRENT_REQUESTS is 0 and every request goes straight through
+    slots: Option<Arc<Semaphore>>,
+    // None when REQUEST_TIMEOUT_SECS is 0 and a request waits as long as it takes
+    wait: Option<Duration>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrent_requests: usize, wait: Duration) -> Self {
+        Self {
+            slots: (max_concurrent_requests > 0).then(|| {
+                Arc::new(Semaphore::new(
+                    max_concurrent_requests.min(Semaphore::MAX_PERMITS),
+                ))
+            }),
+            wait: (!wait.is_zero()).then_some(wait),
+        }
+    }
+}
+
+// middleware function which holds a slot for the lifetime of the request, a request that can't
+// get one within the wait is answered with a 503
+pub async fn limit_requests(
+    State(limit): State<ConcurrencyLimit>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(slots) = limit.slots else {
+        return Ok(next.run(request).await);
+    };
+
+    let permit = match limit.wait {
+        Some(wait) => match timeout(wait, slots.acquire_owned()).await {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!("shed request, no concurrency slot free within {:?}", wait);
+                return Err(AppError::Overloaded);
+            }
+        },
+        None => slots.acquire_owned().await,
+    };
+    // the semaphore is never closed
+    let _permit = permit.expect("the concurrency semaphore is open");
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    // how long "/hold" keeps the only slot
+    const HOLD: Duration = Duration::from_millis(100);
+
+    // a router with a single slot, "/hold" keeps it for HOLD and "/" answers at once
+    fn app(wait: Duration) -> Router {
+        Router::new()
+            .route("/hold", get(|| tokio::time::sleep(HOLD)))
+            .route("/", get(|| async {}))
+            .layer(middleware::from_fn_with_state(
+                ConcurrencyLimit::new(1, wait),
+                limit_requests,
+            ))
+    }
+
+    // the status of a request to "/", sent after the "/hold" request has taken the slot
+    async fn status_behind_hold(wait: Duration) -> StatusCode {
+        let app = app(wait);
+        let holding = tokio::spawn(app.clone().oneshot(request("/hold")));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let response = app.oneshot(request("/")).await.expect("a response");
+        let held = holding.await.expect("the holder runs").expect("a response");
+        assert_eq!(held.status(), StatusCode::OK);
+        response.status()
+    }
+
+    fn request(path: &str) -> Request {
+        Request::builder()
+            .uri(path)
+            .body(Body::empty())
+            .expect("a valid request")
+    }
+
+    #[tokio::test]
+    async fn a_request_waiting_for_a_slot_is_admitted_once_one_frees() {
+        assert_eq!(
+            status_behind_hold(Duration::from_secs(5)).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn a_request_that_waits_too_long_is_shed() {
+        assert_eq!(
+            status_behind_hold(Duration::from_millis(20)).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+}
@@ -0,0 +1,904 @@
+// config.rs
+// runtime configuration for the API, read from environment variables and the env file at startup
+// every setting has a sensible default so the API runs with no environment at all
+// the env file, .env or the one ENV_FILE names, holds settings in NAME=value lines, a variable
+// set in the process environment wins over the file, and the file is read again by a reload,
+// which is how a setting changes while the server runs, the process environment can't be
+// changed from outside
+
+use crate::redact;
+use axum::http::HeaderValue;
+use color_eyre::eyre::{eyre, Result};
+use ipnet::IpNet;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+// env file read when ENV_FILE doesn't name another
+const DEFAULT_ENV_FILE: &str = ".env";
+
+// the SQLite database connected to when DATABASE_URL isn't set
+const DEFAULT_DATABASE_URL: &str = "sqlite://db/test.db";
+
+// placeholder message used by create_data when a request doesn't supply one
+const DEFAULT_MESSAGE: &str = "No message provided.";
+
+// socket defaults, Nagle is disabled and idle connections are probed after a minute
+const DEFAULT_TCP_NODELAY: bool = true;
+const DEFAULT_TCP_KEEPALIVE_SECS: u64 = 60;
+
+// HTTP connection defaults, a request's headers must arrive within 10 seconds of its first
+// byte and a connection is kept open for further requests
+const DEFAULT_HEADER_READ_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_HTTP_KEEP_ALIVE: bool = true;
+
+// largest number of records accepted by a single batch request
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+// retries for a write that fails with SQLITE_BUSY, and the wait before the first retry
+const DEFAULT_BUSY_RETRIES: u32 = 3;
+const DEFAULT_BUSY_RETRY_BACKOFF_MS: u64 = 25;
+
+// page size used by read_data when a page is requested without a limit, and the largest allowed
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const DEFAULT_MAX_PAGE_SIZE: i64 = 500;
+
+// Cache-Control sent with successful responses from the read routes
+const DEFAULT_READ_CACHE_CONTROL: &str = "public, max-age=5";
+
+// how often the background stats task logs pool and request counts
+const DEFAULT_STATS_INTERVAL_SECS: u64 = 60;
+
+// longest a single database query may run before the request fails with a 504
+const DEFAULT_SQL_TIMEOUT_MS: u64 = 5000;
+
+// most requests handled at once, an extra request is shed with a 503
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 1024;
+
+// free space the database's filesystem needs for /readyz to report ready
+const DEFAULT_MIN_DISK_FREE_MB: u64 = 100;
+
+// longest request URI accepted, in bytes
+const DEFAULT_MAX_URI_LEN: usize = 8 * 1024;
+
+// webhook delivery, attempts before an event is dead-lettered, the wait before the first
+// retry (doubled after each failure) and how often the outbox is polled
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: u32 = 8;
+const DEFAULT_WEBHOOK_RETRY_BACKOFF_SECS: u64 = 5;
+const DEFAULT_OUTBOX_POLL_INTERVAL_MS: u64 = 1000;
+
+// TCP port the server listens on, on 127.0.0.1
+const DEFAULT_PORT: u16 = 3000;
+
+// most records an unpaginated /database_read returns
+const DEFAULT_READ_HARD_LIMIT: i64 = 10_000;
+
+// records the write queue holds before creates are shed with a 503
+const DEFAULT_ASYNC_WRITE_QUEUE_SIZE: usize = 1000;
+
+// longest the write queue may take to flush on shutdown
+const DEFAULT_SHUTDOWN_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+// messages shorter than this are stored as plain text even with COMPRESS_MESSAGES
+const DEFAULT_COMPRESS_MIN_BYTES: usize = 1024;
+
+// longest a CRUD request may run before it's answered with a 503
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 5;
+
+// longest a bulk request (batch update, import, vacuum, stream) may run
+const DEFAULT_BULK_REQUEST_TIMEOUT_SECS: u64 = 300;
+
+// prepared statements cached per connection, SQLx's own default
+const DEFAULT_STATEMENT_CACHE_CAPACITY: usize = 100;
+
+// how long a reservation made with /database_reserve lasts, and how often expired ones are
+// swept from the table
+const DEFAULT_RESERVATION_TTL_SECS: u64 = 300;
+const DEFAULT_RESERVATION_SWEEP_INTERVAL_SECS: u64 = 60;
+
+// Content-Security-Policy of HTML responses, the built-in pages only load scripts, styles and
+// data from this server and can't be framed
+const DEFAULT_CONTENT_SECURITY_POLICY: &str =
+    "default-src 'self'; base-uri 'none'; form-action 'self'; frame-ancestors 'none'";
+
+// records written per transaction by the streaming NDJSON import
+const DEFAULT_IMPORT_BATCH_SIZE: usize = 500;
+
+// pragmas SQLITE_PRAGMAS may set, tuning knobs that can't change what a statement does or
+// reach outside the database file
+const ALLOWED_PRAGMAS: &[&str] = &[
+    "busy_timeout",
+    "cache_size",
+    "cache_spill",
+    "journal_size_limit",
+    "mmap_size",
+    "synchronous",
+    "temp_store",
+    "wal_autocheckpoint",
+];
+
+// struct to hold the resolved configuration
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub default_message: String,
+    pub tcp_nodelay: bool,
+    // 0 disables TCP keepalive
+    pub tcp_keepalive_secs: u64,
+    // 0 disables the limit
+    pub header_read_timeout_secs: u64,
+    // false closes each connection after its first response
+    pub http_keep_alive: bool,
+    // key required by guarded routes, when unset those routes reject every request
+    pub api_key: Option<String>,
+    pub max_batch_size: usize,
+    // route panic reports through tracing instead of printing them to stderr
+    pub log_panics: bool,
+    // serve over this Unix domain socket instead of TCP, Unix platforms only
+    pub unix_socket_path: Option<String>,
+    // connection string of the SQLite database, masked wherever it's logged or shown
+    pub database_url: String,
+    // 0 disables retrying writes when the database is busy
+    pub busy_retries: u32,
+    pub busy_retry_backoff_ms: u64,
+    // show record messages as "<redacted N bytes>" in log output
+    pub log_redact_messages: bool,
+    pub default_page_size: i64,
+    pub max_page_size: i64,
+    // client networks allowed to use the API, empty allows everyone
+    pub allowed_ips: Vec<IpNet>,
+    // take the client IP from X-Forwarded-For, only safe behind a proxy that sets it
+    pub trust_proxy: bool,
+    pub read_cache_control: HeaderValue,
+    // write record ids as JSON strings instead of numbers
+    pub id_as_string: bool,
+    // 0 disables the background stats task
+    pub stats_interval_secs: u64,
+    // 0 disables the database query timeout
+    pub sql_timeout_ms: u64,
+    // 0 disables the concurrency limit
+    pub max_concurrent_requests: usize,
+    // 0 disables the free disk space check in /readyz
+    pub min_disk_free_mb: u64,
+    // add a Server-Timing header with database and serialization times
+    pub server_timing: bool,
+    // 0 disables the URI length check
+    pub max_uri_len: usize,
+    // events are written to the outbox and POSTed here, no events are kept when unset
+    pub webhook_url: Option<String>,
+    pub webhook_max_attempts: u32,
+    pub webhook_retry_backoff_secs: u64,
+    pub outbox_poll_interval_ms: u64,
+    pub port: u16,
+    // 0 lets an unpaginated read return the whole table
+    pub read_hard_limit: i64,
+    // 0 disables the scheduled vacuum, POST /admin/vacuum still works
+    pub vacuum_interval_secs: u64,
+    // wrap JSON responses as {"success": ..., "data" or "error": ...}
+    pub envelope_responses: bool,
+    // format of the per-request access log, off by default
+    pub access_log: AccessLog,
+    // how log lines are written, for people reading them or for a log collector
+    pub log_format: LogFormat,
+    // queue creates and store them in the background, answering 202 straight away
+    pub async_writes: bool,
+    pub async_write_queue_size: usize,
+    // reject updates and deletes that carry no If-Match header with a 428
+    pub require_if_match: bool,
+    // development turns on SQL statement logging with bound parameters
+    pub app_env: AppEnv,
+    pub shutdown_drain_timeout_secs: u64,
+    // store long messages zstd compressed
+    pub compress_messages: bool,
+    pub compress_min_bytes: usize,
+    // per-route request timeouts, 0 disables them
+    pub request_timeout_secs: u64,
+    pub bulk_request_timeout_secs: u64,
+    // most records the test table may hold, creates beyond it get a 403, 0 is unlimited
+    pub max_records: i64,
+    // how many of the slowest requests GET /admin/slowest keeps, 0 turns it off
+    pub slowest_requests: usize,
+    // append "; charset=utf-8" to JSON and text Content-Types
+    pub content_type_charset: bool,
+    // prepared statements SQLx keeps per database connection
+    pub statement_cache_capacity: usize,
+    // fault injection, only accepted with APP_ENV=development, see chaos.rs
+    pub chaos_delay_ms: u64,
+    pub chaos_error_rate: f64,
+    // what a request for a path with a trailing slash gets, see trailing_slash.rs
+    pub trailing_slash: TrailingSlash,
+    pub reservation_ttl_secs: u64,
+    // 0 disables the sweeper, expired reservations are then kept but can't be confirmed
+    pub reservation_sweep_interval_secs: u64,
+    // an empty value leaves the header off
+    pub content_security_policy: HeaderValue,
+    // records the NDJSON import writes per transaction
+    pub import_batch_size: usize,
+    // pragmas applied to every new database connection, in order
+    pub sqlite_pragmas: Vec<SqlitePragma>,
+    // the Server header sent with every response, None strips it
+    pub server_header: Option<HeaderValue>,
+    // seconds between a shutdown signal and the server stopping, new requests get a 503 meanwhile
+    pub shutdown_grace_secs: u64,
+}
+
+// formats the access log can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessLog {
+    Off,
+    // Apache combined log format
+    Combined,
+}
+
+impl AccessLog {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AccessLog::Off => "off",
+            AccessLog::Combined => "combined",
+        }
+    }
+}
+
+impl FromStr for AccessLog {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(AccessLog::Off),
+            "combined" => Ok(AccessLog::Combined),
+            _ => Err(()),
+        }
+    }
+}
+
+// formats log lines can be written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    // human-readable lines, one per event
+    Pretty,
+    // one JSON object per line
+    Json,
+}
+
+impl LogFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogFormat::Pretty => "pretty",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "" | "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+// how a path with a trailing slash, like "/database_read/", is treated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+    // routed as if the slash weren't there
+    Strip,
+    // answered with a 308 to the path without the slash
+    Redirect,
+    // routed as it is, so it's a 404
+    Off,
+}
+
+impl TrailingSlash {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrailingSlash::Strip => "strip",
+            TrailingSlash::Redirect => "redirect",
+            TrailingSlash::Off => "off",
+        }
+    }
+}
+
+impl FromStr for TrailingSlash {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "strip" => Ok(TrailingSlash::Strip),
+            "redirect" => Ok(TrailingSlash::Redirect),
+            "off" => Ok(TrailingSlash::Off),
+            _ => Err(()),
+        }
+    }
+}
+
+// a SQLite pragma applied to every new database connection, the name is one of ALLOWED_PRAGMAS
+// and the value an integer or a keyword, so together they can't form any other statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqlitePragma {
+    pub name: &'static str,
+    pub value: String,
+}
+
+impl fmt::Display for SqlitePragma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
+
+// environments the API can run in, only development may log SQL parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppEnv {
+    Development,
+    Production,
+}
+
+impl AppEnv {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AppEnv::Development => "development",
+            AppEnv::Production => "production",
+        }
+    }
+}
+
+impl FromStr for AppEnv {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(AppEnv::Development),
+            "" | "production" | "prod" => Ok(AppEnv::Production),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Config {
+    // build the configuration from the environment and the env file, falling back to defaults
+    // a variable that is set but can't be parsed is an error
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(&Vars::load()?)
+    }
+
+    fn from_vars(vars: &Vars) -> Result<Self> {
+        let default_message = vars
+            .get("DEFAULT_MESSAGE")
+            .unwrap_or_else(|| DEFAULT_MESSAGE.to_string());
+        let tcp_nodelay = parse_var(vars, "TCP_NODELAY", DEFAULT_TCP_NODELAY)?;
+        let tcp_keepalive_secs = parse_var(vars, "TCP_KEEPALIVE_SECS", DEFAULT_TCP_KEEPALIVE_SECS)?;
+        let header_read_timeout_secs = parse_var(
+            vars,
+            "HEADER_READ_TIMEOUT_SECS",
+            DEFAULT_HEADER_READ_TIMEOUT_SECS,
+        )?;
+        let http_keep_alive = parse_var(vars, "HTTP_KEEP_ALIVE", DEFAULT_HTTP_KEEP_ALIVE)?;
+        let api_key = optional_var(vars, "API_KEY");
+        let max_batch_size = parse_var(vars, "MAX_BATCH_SIZE", DEFAULT_MAX_BATCH_SIZE)?;
+        let log_panics = parse_var(vars, "LOG_PANICS", true)?;
+        let unix_socket_path = optional_var(vars, "UNIX_SOCKET_PATH");
+        let database_url =
+            optional_var(vars, "DATABASE_URL").unwrap_or_else(|| DEFAULT_DATABASE_URL.to_string());
+        if !database_url.starts_with("sqlite:") {
+            return Err(eyre!(
+                "DATABASE_URL ({}) must be a sqlite: URL",
+                redact::connection_string(&database_url)
+            ));
+        }
+        let busy_retries = parse_var(vars, "BUSY_RETRIES", DEFAULT_BUSY_RETRIES)?;
+        let busy_retry_backoff_ms =
+            parse_var(vars, "BUSY_RETRY_BACKOFF_MS", DEFAULT_BUSY_RETRY_BACKOFF_MS)?;
+        let log_redact_messages = parse_var(vars, "LOG_REDACT_MESSAGES", true)?;
+        let default_page_size = parse_var(vars, "DEFAULT_PAGE_SIZE", DEFAULT_PAGE_SIZE)?;
+        let max_page_size = parse_var(vars, "MAX_PAGE_SIZE", DEFAULT_MAX_PAGE_SIZE)?;
+        if default_page_size < 1 || default_page_size > max_page_size {
+            return Err(eyre!(
+                "DEFAULT_PAGE_SIZE ({}) must be between 1 and MAX_PAGE_SIZE ({})",
+                default_page_size,
+                max_page_size
+            ));
+        }
+        let allowed_ips = parse_networks(vars, "ALLOWED_IPS")?;
+        let trust_proxy = parse_var(vars, "TRUST_PROXY", false)?;
+        let read_cache_control = parse_var(
+            vars,
+            "READ_CACHE_CONTROL",
+            HeaderValue::from_static(DEFAULT_READ_CACHE_CONTROL),
+        )?;
+        let id_as_string = parse_var(vars, "ID_AS_STRING", false)?;
+        let stats_interval_secs =
+            parse_var(vars, "STATS_INTERVAL_SECS", DEFAULT_STATS_INTERVAL_SECS)?;
+        let sql_timeout_ms = parse_var(vars, "SQL_TIMEOUT_MS", DEFAULT_SQL_TIMEOUT_MS)?;
+        let max_concurrent_requests = parse_var(
+            vars,
+            "MAX_CONCURRENT_REQUESTS",
+            DEFAULT_MAX_CONCURRENT_REQUESTS,
+        )?;
+        let min_disk_free_mb = parse_var(vars, "MIN_DISK_FREE_MB", DEFAULT_MIN_DISK_FREE_MB)?;
+        let server_timing = parse_var(vars, "SERVER_TIMING", false)?;
+        let max_uri_len = parse_var(vars, "MAX_URI_LEN", DEFAULT_MAX_URI_LEN)?;
+        let webhook_url = optional_var(vars, "WEBHOOK_URL");
+        let webhook_max_attempts =
+            parse_var(vars, "WEBHOOK_MAX_ATTEMPTS", DEFAULT_WEBHOOK_MAX_ATTEMPTS)?;
+        let webhook_retry_backoff_secs = parse_var(
+            vars,
+            "WEBHOOK_RETRY_BACKOFF_SECS",
+            DEFAULT_WEBHOOK_RETRY_BACKOFF_SECS,
+        )?;
+        let outbox_poll_interval_ms = parse_var(
+            vars,
+            "OUTBOX_POLL_INTERVAL_MS",
+            DEFAULT_OUTBOX_POLL_INTERVAL_MS,
+        )?;
+        let port = parse_var(vars, "PORT", DEFAULT_PORT)?;
+        let read_hard_limit = parse_var(vars, "READ_HARD_LIMIT", DEFAULT_READ_HARD_LIMIT)?;
+        let vacuum_interval_secs = parse_var(vars, "VACUUM_INTERVAL_SECS", 0)?;
+        let envelope_responses = parse_var(vars, "ENVELOPE_RESPONSES", false)?;
+        let access_log = parse_var(vars, "ACCESS_LOG", AccessLog::Off)?;
+        let log_format = parse_var(vars, "LOG_FORMAT", LogFormat::Pretty)?;
+        let async_writes = parse_var(vars, "ASYNC_WRITES", false)?;
+        let async_write_queue_size = parse_var(
+            vars,
+            "ASYNC_WRITE_QUEUE_SIZE",
+            DEFAULT_ASYNC_WRITE_QUEUE_SIZE,
+        )?;
+        let require_if_match = parse_var(vars, "REQUIRE_IF_MATCH", false)?;
+        let app_env = parse_var(vars, "APP_ENV", AppEnv::Production)?;
+        let shutdown_drain_timeout_secs = parse_var(
+            vars,
+            "SHUTDOWN_DRAIN_TIMEOUT_SECS",
+            DEFAULT_SHUTDOWN_DRAIN_TIMEOUT_SECS,
+        )?;
+        let compress_messages = parse_var(vars, "COMPRESS_MESSAGES", false)?;
+        let compress_min_bytes = parse_var(vars, "COMPRESS_MIN_BYTES", DEFAULT_COMPRESS_MIN_BYTES)?;
+        let request_timeout_secs =
+            parse_var(vars, "REQUEST_TIMEOUT_SECS", DEFAULT_REQUEST_TIMEOUT_SECS)?;
+        let bulk_request_timeout_secs = parse_var(
+            vars,
+            "BULK_REQUEST_TIMEOUT_SECS",
+            DEFAULT_BULK_REQUEST_TIMEOUT_SECS,
+        )?;
+        let max_records = parse_var(vars, "MAX_RECORDS", 0)?;
+        if max_records < 0 {
+            return Err(eyre!("MAX_RECORDS ({}) must not be negative", max_records));
+        }
+        let slowest_requests = parse_var(vars, "SLOWEST_REQUESTS", 0)?;
+        let content_type_charset = parse_var(vars, "CONTENT_TYPE_CHARSET", true)?;
+        let statement_cache_capacity = parse_var(
+            vars,
+            "STATEMENT_CACHE_CAPACITY",
+            DEFAULT_STATEMENT_CACHE_CAPACITY,
+        )?;
+        let chaos_delay_ms = parse_var(vars, "CHAOS_DELAY_MS", 0)?;
+        let chaos_error_rate: f64 = parse_var(vars, "CHAOS_ERROR_RATE", 0.0)?;
+        if !(0.0..=1.0).contains(&chaos_error_rate) {
+            return Err(eyre!(
+                "CHAOS_ERROR_RATE ({}) must be between 0 and 1",
+                chaos_error_rate
+            ));
+        }
+        if (chaos_delay_ms > 0 || chaos_error_rate > 0.0) && app_env != AppEnv::Development {
+            return Err(eyre!(
+                "CHAOS_DELAY_MS and CHAOS_ERROR_RATE can only be set with APP_ENV=development"
+            ));
+        }
+        let trailing_slash = parse_var(vars, "TRAILING_SLASH", TrailingSlash::Strip)?;
+        let reservation_ttl_secs =
+            parse_var(vars, "RESERVATION_TTL_SECS", DEFAULT_RESERVATION_TTL_SECS)?;
+        let reservation_sweep_interval_secs = parse_var(
+            vars,
+            "RESERVATION_SWEEP_INTERVAL_SECS",
+            DEFAULT_RESERVATION_SWEEP_INTERVAL_SECS,
+        )?;
+        let content_security_policy = parse_var(
+            vars,
+            "CONTENT_SECURITY_POLICY",
+            HeaderValue::from_static(DEFAULT_CONTENT_SECURITY_POLICY),
+        )?;
+        let import_batch_size = parse_var(vars, "IMPORT_BATCH_SIZE", DEFAULT_IMPORT_BATCH_SIZE)?;
+        if import_batch_size < 1 {
+            return Err(eyre!("IMPORT_BATCH_SIZE must be at least 1"));
+        }
+        let sqlite_pragmas = parse_pragmas(vars, "SQLITE_PRAGMAS")?;
+        let server_header = optional_var(vars, "SERVER_HEADER")
+            .map(|value| {
+                HeaderValue::from_str(&value)
+                    .map_err(|_| eyre!("invalid value for SERVER_HEADER: {:?}", value))
+            })
+            .transpose()?;
+        let shutdown_grace_secs = parse_var(vars, "SHUTDOWN_GRACE_SECS", 0)?;
+
+        Ok(Self {
+            default_message,
+            tcp_nodelay,
+            tcp_keepalive_secs,
+            header_read_timeout_secs,
+            http_keep_alive,
+            api_key,
+            max_batch_size,
+            log_panics,
+            unix_socket_path,
+            database_url,
+            busy_retries,
+            busy_retry_backoff_ms,
+            log_redact_messages,
+            default_page_size,
+            max_page_size,
+            allowed_ips,
+            trust_proxy,
+            read_cache_control,
+            id_as_string,
+            stats_interval_secs,
+            sql_timeout_ms,
+            max_concurrent_requests,
+            min_disk_free_mb,
+            server_timing,
+            max_uri_len,
+            webhook_url,
+            webhook_max_attempts,
+            webhook_retry_backoff_secs,
+            outbox_poll_interval_ms,
+            port,
+            read_hard_limit,
+            vacuum_interval_secs,
+            envelope_responses,
+            access_log,
+            log_format,
+            async_writes,
+            async_write_queue_size,
+            require_if_match,
+            app_env,
+            shutdown_drain_timeout_secs,
+            compress_messages,
+            compress_min_bytes,
+            request_timeout_secs,
+            bulk_request_timeout_secs,
+            max_records,
+            slowest_requests,
+            content_type_charset,
+            statement_cache_capacity,
+            chaos_delay_ms,
+            chaos_error_rate,
+            trailing_slash,
+            reservation_ttl_secs,
+            reservation_sweep_interval_secs,
+            content_security_policy,
+            import_batch_size,
+            sqlite_pragmas,
+            server_header,
+            shutdown_grace_secs,
+        })
+    }
+
+    // re-read the env file and the environment for a reload, settings which are only applied at
+    // startup keep their current values and the names of any that changed are returned alongside
+    pub fn reload(&self) -> Result<(Self, Vec<&'static str>)> {
+        self.reload_from(&Vars::load()?)
+    }
+
+    fn reload_from(&self, vars: &Vars) -> Result<(Self, Vec<&'static str>)> {
+        let mut config = Self::from_vars(vars)?;
+        let mut restart_required = Vec::new();
+
+        macro_rules! keep {
+            ($($field:ident => $name:literal),* $(,)?) => {
+                $(
+                    if config.$field != self.$field {
+                        restart_required.push($name);
+                        config.$field = self.$field.clone();
+                    }
+                )*
+            };
+        }
+        keep! {
+            tcp_nodelay => "TCP_NODELAY",
+            tcp_keepalive_secs => "TCP_KEEPALIVE_SECS",
+            header_read_timeout_secs => "HEADER_READ_TIMEOUT_SECS",
+            http_keep_alive => "HTTP_KEEP_ALIVE",
+            log_panics => "LOG_PANICS",
+            unix_socket_path => "UNIX_SOCKET_PATH",
+            database_url => "DATABASE_URL",
+            read_cache_control => "READ_CACHE_CONTROL",
+            stats_interval_secs => "STATS_INTERVAL_SECS",
+            max_concurrent_requests => "MAX_CONCURRENT_REQUESTS",
+            webhook_url => "WEBHOOK_URL",
+            webhook_max_attempts => "WEBHOOK_MAX_ATTEMPTS",
+            webhook_retry_backoff_secs => "WEBHOOK_RETRY_BACKOFF_SECS",
+            outbox_poll_interval_ms => "OUTBOX_POLL_INTERVAL_MS",
+            port => "PORT",
+            vacuum_interval_secs => "VACUUM_INTERVAL_SECS",
+            async_writes => "ASYNC_WRITES",
+            async_write_queue_size => "ASYNC_WRITE_QUEUE_SIZE",
+            app_env => "APP_ENV",
+            log_format => "LOG_FORMAT",
+            shutdown_drain_timeout_secs => "SHUTDOWN_DRAIN_TIMEOUT_SECS",
+            request_timeout_secs => "REQUEST_TIMEOUT_SECS",
+            bulk_request_timeout_secs => "BULK_REQUEST_TIMEOUT_SECS",
+            statement_cache_capacity => "STATEMENT_CACHE_CAPACITY",
+            reservation_sweep_interval_secs => "RESERVATION_SWEEP_INTERVAL_SECS",
+            content_security_policy => "CONTENT_SECURITY_POLICY",
+            sqlite_pragmas => "SQLITE_PRAGMAS",
+            server_header => "SERVER_HEADER",
+            shutdown_grace_secs => "SHUTDOWN_GRACE_SECS",
+        }
+
+        Ok((config, restart_required))
+    }
+
+    // the database file DATABASE_URL names, for the checks that look at the file itself,
+    // "sqlite://db/test.db?mode=rwc" is "db/test.db"
+    pub fn database_file(&self) -> &str {
+        let url = self.database_url.split('?').next().unwrap_or_default();
+        url.strip_prefix("sqlite://")
+            .or_else(|| url.strip_prefix("sqlite:"))
+            .unwrap_or(url)
+    }
+
+    // the effective settings as JSON, secrets are replaced by whether they're set
+    pub fn redacted(&self) -> Value {
+        json!({
+            "default_message": self.default_message,
+            "tcp_nodelay": self.tcp_nodelay,
+            "tcp_keepalive_secs": self.tcp_keepalive_secs,
+            "header_read_timeout_secs": self.header_read_timeout_secs,
+            "http_keep_alive": self.http_keep_alive,
+            "api_key": self.api_key.as_ref().map(|_| "<redacted>"),
+            "max_batch_size": self.max_batch_size,
+            "log_panics": self.log_panics,
+            "unix_socket_path": self.unix_socket_path,
+            "database_url": redact::connection_string(&self.database_url),
+            "busy_retries": self.busy_retries,
+            "busy_retry_backoff_ms": self.busy_retry_backoff_ms,
+            "log_redact_messages": self.log_redact_messages,
+            "default_page_size": self.default_page_size,
+            "max_page_size": self.max_page_size,
+            "allowed_ips": self.allowed_ips.iter().map(IpNet::to_string).collect::<Vec<_>>(),
+            "trust_proxy": self.trust_proxy,
+            "read_cache_control": self.read_cache_control.to_str().unwrap_or_default(),
+            "id_as_string": self.id_as_string,
+            "stats_interval_secs": self.stats_interval_secs,
+            "sql_timeout_ms": self.sql_timeout_ms,
+            "max_concurrent_requests": self.max_concurrent_requests,
+            "min_disk_free_mb": self.min_disk_free_mb,
+            "server_timing": self.server_timing,
+            "max_uri_len": self.max_uri_len,
+            "webhook_url": self.webhook_url.as_ref().map(|_| "<redacted>"),
+            "webhook_max_attempts": self.webhook_max_attempts,
+            "webhook_retry_backoff_secs": self.webhook_retry_backoff_secs,
+            "outbox_poll_interval_ms": self.outbox_poll_interval_ms,
+            "port": self.port,
+            "read_hard_limit": self.read_hard_limit,
+            "vacuum_interval_secs": self.vacuum_interval_secs,
+            "envelope_responses": self.envelope_responses,
+            "access_log": self.access_log.as_str(),
+            "log_format": self.log_format.as_str(),
+            "async_writes": self.async_writes,
+            "async_write_queue_size": self.async_write_queue_size,
+            "require_if_match": self.require_if_match,
+            "app_env": self.app_env.as_str(),
+            "shutdown_drain_timeout_secs": self.shutdown_drain_timeout_secs,
+            "compress_messages": self.compress_messages,
+            "compress_min_bytes": self.compress_min_bytes,
+            "request_timeout_secs": self.request_timeout_secs,
+            "bulk_request_timeout_secs": self.bulk_request_timeout_secs,
+            "max_records": self.max_records,
+            "slowest_requests": self.slowest_requests,
+            "content_type_charset": self.content_type_charset,
+            "statement_cache_capacity": self.statement_cache_capacity,
+            "chaos_delay_ms": self.chaos_delay_ms,
+            "chaos_error_rate": self.chaos_error_rate,
+            "trailing_slash": self.trailing_slash.as_str(),
+            "reservation_ttl_secs": self.reservation_ttl_secs,
+            "reservation_sweep_interval_secs": self.reservation_sweep_interval_secs,
+            "content_security_policy": self.content_security_policy.to_str().unwrap_or_default(),
+            "import_batch_size": self.import_batch_size,
+            "sqlite_pragmas": self.sqlite_pragmas.iter().map(SqlitePragma::to_string).collect::<Vec<_>>(),
+            "server_header": self.server_header.as_ref().and_then(|value| value.to_str().ok()),
+            "shutdown_grace_secs": self.shutdown_grace_secs,
+        })
+    }
+}
+
+// the live configuration, swapped as a whole by /admin/reload, each request works from
+// the snapshot it loaded so a reload never changes settings part way through a request
+#[derive(Clone, Debug)]
+pub struct SharedConfig(Arc<RwLock<Arc<Config>>>);
+
+impl SharedConfig {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self(Arc::new(RwLock::new(config)))
+    }
+
+    // the current configuration
+    pub fn load(&self) -> Arc<Config> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    // replace the configuration seen by subsequent requests
+    pub fn store(&self, config: Arc<Config>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+}
+
+// struct to hold the settings read from the env file, looked up after the process environment
+#[derive(Default)]
+struct Vars {
+    file: HashMap<String, String>,
+}
+
+impl Vars {
+    // the environment and the env file ENV_FILE names, or .env, a missing file is no settings
+    fn load() -> Result<Self> {
+        let path = env::var("ENV_FILE").unwrap_or_else(|_| DEFAULT_ENV_FILE.to_string());
+        Self::read(Path::new(&path))
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let lines = match dotenvy::from_path_iter(path) {
+            Ok(lines) => lines,
+            Err(e) if e.not_found() => return Ok(Self::default()),
+            Err(e) => return Err(eyre!("could not read env file {:?}: {}", path, e)),
+        };
+        let file = lines
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map_err(|e| eyre!("invalid env file {:?}: {}", path, e))?;
+        Ok(Self { file })
+    }
+
+    // a setting's value, from the process environment when it's set there
+    fn get(&self, name: &str) -> Option<String> {
+        env::var(name).ok().or_else(|| self.file.get(name).cloned())
+    }
+}
+
+// read a setting and parse it, returning the default when it's unset
+fn parse_var<T: FromStr>(vars: &Vars, name: &str, default: T) -> Result<T> {
+    match vars.get(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| eyre!("invalid value for {}: {:?}", name, value)),
+        None => Ok(default),
+    }
+}
+
+// read an optional setting, treating an empty value as unset
+fn optional_var(vars: &Vars, name: &str) -> Option<String> {
+    vars.get(name).filter(|value| !value.is_empty())
+}
+
+// read a comma-separated list of CIDR ranges, a bare address is treated as a single host
+fn parse_networks(vars: &Vars, name: &str) -> Result<Vec<IpNet>> {
+    let Some(value) = optional_var(vars, name) else {
+        return Ok(Vec::new());
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| eyre!("invalid value for {}: {:?}", name, entry))
+        })
+        .collect()
+}
+
+// read a comma-separated list of name=value pragmas, refusing names that aren't allowed and
+// values that aren't a plain integer or keyword
+fn parse_pragmas(vars: &Vars, name: &str) -> Result<Vec<SqlitePragma>> {
+    let Some(value) = optional_var(vars, name) else {
+        return Ok(Vec::new());
+    };
+
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (pragma, setting) = entry.split_once('=').ok_or_else(|| {
+                eyre!(
+                    "invalid value for {}: {:?}, expected name=value",
+                    name,
+                    entry
+                )
+            })?;
+            let pragma = pragma.trim().to_ascii_lowercase();
+            let pragma = ALLOWED_PRAGMAS
+                .iter()
+                .find(|allowed| **allowed == pragma)
+                .ok_or_else(|| {
+                    eyre!(
+                        "{} can't set pragma {:?}, allowed are {}",
+                        name,
+                        pragma,
+                        ALLOWED_PRAGMAS.join(", ")
+                    )
+                })?;
+            let setting = setting.trim();
+            let digits = setting.strip_prefix('-').unwrap_or(setting);
+            let is_integer = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+            let is_keyword =
+                !setting.is_empty() && setting.bytes().all(|b| b.is_ascii_alphabetic());
+            if !is_integer && !is_keyword {
+                return Err(eyre!(
+                    "invalid value for {} pragma {}: {:?}, expected an integer or a keyword",
+                    name,
+                    pragma,
+                    setting
+                ));
+            }
+            Ok(SqlitePragma {
+                name: pragma,
+                value: setting.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // an env file of its own for each test, removed again by the test
+    fn env_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = env::temp_dir().join(format!(
+            "axum-api-dbase-{}-{}.env",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).expect("the env file writes");
+        path
+    }
+
+    #[test]
+    fn reload_picks_up_a_changed_env_file() {
+        let path = env_file("reload", "DEFAULT_PAGE_SIZE=10\n");
+        let config = Config::from_vars(&Vars::read(&path).expect("the env file reads"))
+            .expect("the configuration builds");
+        assert_eq!(config.default_page_size, 10);
+
+        fs::write(&path, "DEFAULT_PAGE_SIZE=20\nPORT=4000\n").expect("the env file writes");
+        let (reloaded, restart_required) = config
+            .reload_from(&Vars::read(&path).expect("the env file reads"))
+            .expect("the configuration reloads");
+        fs::remove_file(&path).expect("the env file is removed");
+
+        assert_eq!(reloaded.default_page_size, 20);
+        assert_eq!(reloaded.port, config.port);
+        assert_eq!(restart_required, ["PORT"]);
+    }
+
+    #[test]
+    fn the_database_file_is_taken_from_the_url() {
+        let path = env_file(
+            "database",
+            "DATABASE_URL=sqlite://data/records.db?mode=rwc\n",
+        );
+        let config = Config::from_vars(&Vars::read(&path).expect("the env file reads"));
+        fs::remove_file(&path).expect("the env file is removed");
+        assert_eq!(
+            config.expect("the configuration builds").database_file(),
+            "data/records.db"
+        );
+    }
+
+    #[test]
+    fn a_missing_env_file_is_no_settings() {
+        let vars = Vars::read(Path::new("/nonexistent/.env")).expect("a missing file is fine");
+        assert!(vars.file.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+// decode.rs
+// row-by-row decoding for read_data, so a message that isn't valid UTF-8 (binary data written
+// to the table by something other than this API) is reported against the row it's in
+// instead of failing the whole read with an opaque decode error, with ?raw=true such messages
+// are returned base64 encoded and marked with "message_encoding": "base64", a date that isn't
+// YYYY-MM-DD is reported the same way
+
+use crate::error::AppError;
+use crate::{compress, TestRecord};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::Row;
+use tracing::error;
+
+// encoding reported for a message that had to be base64 encoded
+const BASE64: &str = "base64";
+
+// struct to hold a record as read_data returns it, the encoding is only present for a
+// message that isn't UTF-8 text
+#[derive(Serialize)]
+pub struct ReadRecord {
+    #[serde(flatten)]
+    record: TestRecord,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_encoding: Option<&'static str>,
+}
+
+impl ReadRecord {
+    // the id of the record, used to key the ?shape=map response
+    pub fn id(&self) -> i32 {
+        self.record.id
+    }
+
+    // the record and how its message is encoded, for the XML form
+    pub fn record(&self) -> &TestRecord {
+        &self.record
+    }
+
+    pub fn message_encoding(&self) -> Option<&'static str> {
+        self.message_encoding
+    }
+}
+
+// decode a row of the test table, raw allows messages that aren't UTF-8
+pub fn record(row: &SqliteRow, raw: bool) -> Result<ReadRecord, AppError> {
+    let id: i32 = row.try_get("id")?;
+    let date: NaiveDate = row
+        .try_get("date")
+        .map_err(|e| undecodable(id, "date", "isn't a YYYY-MM-DD date", e))?;
+
+    let (message, message_encoding) = match compress::load(row) {
+        Ok(message) => (message, None),
+        Err(_) if raw => {
+            let bytes: Vec<u8> = row.try_get("message")?;
+            (STANDARD.encode(bytes), Some(BASE64))
+        }
+        Err(e) => {
+            return Err(undecodable(
+                id,
+                "message",
+                "isn't valid UTF-8 text, read with ?raw=true to get it base64 encoded",
+                e,
+            ))
+        }
+    };
+
+    Ok(ReadRecord {
+        record: TestRecord {
+            id,
+            date,
+            message,
+            updated_at: row.try_get("updated_at")?,
+            created_at: row.try_get("created_at")?,
+        },
+        message_encoding,
+    })
+}
+
+// log which row couldn't be decoded and turn the failure into a 500 that says so
+fn undecodable(id: i32, column: &str, problem: &str, e: sqlx::Error) -> AppError {
+    error!("record {} has a {} that {}: {}", id, column, problem, e);
+    AppError::Undecodable(format!("record {} has a {} that {}", id, column, problem))
+}
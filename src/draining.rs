@@ -0,0 +1,48 @@
+// draining.rs
+// the window between a shutdown signal and the server stopping, SHUTDOWN_GRACE_SECS long
+// requests already in flight run to completion, a request arriving meanwhile is answered with a
+// 503, a Retry-After and Connection: close rather than being dropped when the server stops, so a
+// client or load balancer can take it to another instance during a rolling deploy
+
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// seconds a client refused during the drain is told to wait before trying again
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+// struct to hold whether a shutdown signal has arrived, shared by every request
+#[derive(Clone, Debug)]
+pub struct ShuttingDown(Arc<AtomicBool>);
+
+impl ShuttingDown {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(false)))
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    // called when the shutdown signal arrives, every new request is refused from then on
+    pub fn begin(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+// middleware function which refuses every request once shutdown has begun
+pub async fn reject_while_shutting_down(
+    State(shutting_down): State<ShuttingDown>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if shutting_down.is_shutting_down() {
+        return Err(AppError::ShuttingDown);
+    }
+    Ok(next.run(request).await)
+}
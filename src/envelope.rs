@@ -0,0 +1,104 @@
+// envelope.rs
+// optional response envelope, enabled with ENVELOPE_RESPONSES
+// JSON responses leave the handlers as bare data or problem documents and are wrapped here,
+// as {"success": true, "data": ...} or {"success": false, "error": {...}}, so no handler has to
+// know which style the client wants, other responses (streams, empty bodies) pass untouched
+
+use crate::config::Config;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+// media types of the bodies that get wrapped
+const JSON: &str = "application/json";
+const PROBLEM_JSON: &str = "application/problem+json";
+
+// the envelope every JSON response is wrapped in, exactly one of data and error is present
+#[derive(Serialize, Debug)]
+pub struct Envelope {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<Value>,
+}
+
+impl Envelope {
+    pub fn data(data: Value) -> Self {
+        Envelope {
+            success: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+
+    pub fn error(error: Value) -> Self {
+        Envelope {
+            success: false,
+            data: None,
+            error: Some(error),
+        }
+    }
+}
+
+// middleware function which wraps JSON responses in an Envelope when ENVELOPE_RESPONSES is on
+pub async fn wrap_responses(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    if !config.envelope_responses {
+        return response;
+    }
+
+    let media_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+    let is_error = match media_type.as_deref() {
+        Some(JSON) => false,
+        Some(PROBLEM_JSON) => true,
+        _ => return response,
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("could not read a response body to wrap it: {}", e);
+            return parts.into_response();
+        }
+    };
+    // HEAD responses and the like have nothing to wrap
+    if bytes.is_empty() {
+        return Response::from_parts(parts, Body::empty());
+    }
+    let value = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(value) => value,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+
+    let envelope = if is_error {
+        Envelope::error(value)
+    } else {
+        Envelope::data(value)
+    };
+    let body = serde_json::to_vec(&envelope).expect("an envelope always serializes to JSON");
+    // the wrapped error is no longer a bare problem document
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(JSON));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
@@ -0,0 +1,363 @@
+// error.rs
+// application error type, converts failures into RFC 7807 application/problem+json responses
+// every request gets an id, the client's X-Request-Id when it sent a usable one, which is echoed
+// back and carried by its problem documents, a database or file error's own text is only shown to
+// the client with APP_ENV=development, otherwise it's logged against the request id and the client
+// gets a generic detail and the id to quote
+
+use crate::config::{AppEnv, Config};
+use crate::{draining, startup};
+use axum::{
+    extract::rejection::{BytesRejection, JsonRejection, PathRejection, QueryRejection},
+    extract::{Request, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::{json, Value};
+use std::fmt::Display;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+// media type of RFC 7807 problem documents
+const PROBLEM_JSON: &str = "application/problem+json";
+
+// SQLite's primary result code for a write to a read-only database
+const SQLITE_READONLY: i32 = 8;
+
+// SQLite's extended result codes for a duplicate primary key or unique value
+const SQLITE_CONSTRAINT_PRIMARYKEY: i32 = 1555;
+const SQLITE_CONSTRAINT_UNIQUE: i32 = 2067;
+
+// header a request id is read from and returned in
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+// longest request id taken from a client, a longer or odder one is replaced
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+// ids given to requests that didn't bring one are the time the first was given and a count, so
+// they don't repeat across restarts
+static STARTED: LazyLock<u64> = LazyLock::new(|| {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+});
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+// struct to hold what a problem document needs to know about the request being handled
+#[derive(Clone)]
+struct RequestContext {
+    // reported as a problem's instance
+    path: String,
+    id: String,
+    // APP_ENV=development, database and file errors are shown in full
+    verbose: bool,
+}
+
+tokio::task_local! {
+    static REQUEST: RequestContext;
+}
+
+// errors that can be returned from a handler
+#[derive(Debug)]
+pub enum AppError {
+    // the request was malformed or failed validation
+    BadRequest(String),
+    // the request didn't carry a valid API key
+    Unauthorized,
+    // the client isn't allowed to make this request
+    Forbidden(String),
+    // the request was well formed but its content failed validation, with per-item details
+    Unprocessable(String, Value),
+    // the request body was sent with a Content-Type the route doesn't accept
+    UnsupportedMediaType(String),
+    // the request body is larger than the route accepts
+    PayloadTooLarge(String),
+    // the requested record doesn't exist
+    NotFound(String),
+    // the write collides with an existing record, e.g. a create with an id already in use
+    Conflict(String),
+    // If-Match didn't match the current ETag of the record
+    PreconditionFailed(String),
+    // REQUIRE_IF_MATCH is on and the mutation carried no If-Match header
+    PreconditionRequired,
+    // the database returned an error
+    Database(sqlx::Error),
+    // a file the server works with couldn't be read or written, e.g. an export snapshot
+    Io(io::Error),
+    // a write was refused because the database file or its directory is read-only
+    ReadOnly,
+    // the request URI is longer than MAX_URI_LEN, which is given
+    UriTooLong(usize),
+    // the configuration couldn't be reloaded, the previous one stays in effect
+    InvalidConfig(String),
+    // a stored row couldn't be decoded, e.g. a message that isn't valid UTF-8
+    Undecodable(String),
+    // the server is at its concurrency limit and couldn't admit the request in time
+    Overloaded,
+    // a database query ran longer than SQL_TIMEOUT_MS and was cancelled
+    Timeout,
+    // the route's REQUEST_TIMEOUT_SECS or BULK_REQUEST_TIMEOUT_SECS, which is given, ran out
+    RequestTimeout(u64),
+    // CHAOS_ERROR_RATE picked the request to fail
+    ChaosFailure,
+    // the server is still running its migrations, the database routes aren't available yet
+    Starting,
+    // a Range starts past the end of the export, whose length in bytes is given
+    RangeNotSatisfiable(u64),
+    // a shutdown signal has arrived, the connection is closed after the response
+    ShuttingDown,
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        if is_read_only(&e) {
+            return AppError::ReadOnly;
+        }
+        if is_duplicate(&e) {
+            return AppError::Conflict("a record with this id already exists".to_string());
+        }
+        AppError::Database(e)
+    }
+}
+
+// axum's extractor rejections, so a malformed query string, path or JSON body is answered with a
+// problem document like any other error, the status axum picked is kept where one fits
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<BytesRejection> for AppError {
+    fn from(rejection: BytesRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+fn rejected(status: StatusCode, message: String) -> AppError {
+    match status {
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => AppError::UnsupportedMediaType(message),
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge(message),
+        _ => AppError::BadRequest(message),
+    }
+}
+
+// check whether an error is SQLite's SQLITE_READONLY or one of its extended codes
+pub fn is_read_only(e: &sqlx::Error) -> bool {
+    sqlite_code(e).is_some_and(|code| code & 0xff == SQLITE_READONLY)
+}
+
+// check whether an error is a primary key or unique constraint violation
+fn is_duplicate(e: &sqlx::Error) -> bool {
+    sqlite_code(e).is_some_and(|code| {
+        code == SQLITE_CONSTRAINT_PRIMARYKEY || code == SQLITE_CONSTRAINT_UNIQUE
+    })
+}
+
+// the extended result code of an error returned by SQLite
+fn sqlite_code(e: &sqlx::Error) -> Option<i32> {
+    match e {
+        sqlx::Error::Database(db_error) => {
+            db_error.code().and_then(|code| code.parse::<i32>().ok())
+        }
+        _ => None,
+    }
+}
+
+// turn an AppError into a problem document of the form
+// {"type": "about:blank", "title": "Not Found", "status": 404, "detail": "...", "instance": "/path"}
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let mut errors = None;
+        let mut retry_after = None;
+        let mut close = false;
+        let mut content_range = None;
+        let (status, message) = match self {
+            AppError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+            AppError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "missing or invalid API key".to_string(),
+            ),
+            AppError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            AppError::Conflict(message) => (StatusCode::CONFLICT, message),
+            AppError::PreconditionFailed(message) => (StatusCode::PRECONDITION_FAILED, message),
+            AppError::PreconditionRequired => (
+                StatusCode::PRECONDITION_REQUIRED,
+                "this request must carry an If-Match header with the record's ETag".to_string(),
+            ),
+            AppError::UnsupportedMediaType(message) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, message)
+            }
+            AppError::PayloadTooLarge(message) => (StatusCode::PAYLOAD_TOO_LARGE, message),
+            AppError::UriTooLong(max) => (
+                StatusCode::URI_TOO_LONG,
+                format!("request URI is longer than {} bytes", max),
+            ),
+            AppError::InvalidConfig(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+            AppError::Undecodable(message) => (StatusCode::INTERNAL_SERVER_ERROR, message),
+            // validation failures list every problem found as an "errors" extension member
+            AppError::Unprocessable(message, details) => {
+                errors = Some(details);
+                (StatusCode::UNPROCESSABLE_ENTITY, message)
+            }
+            AppError::Overloaded => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server is busy, try again shortly".to_string(),
+            ),
+            AppError::Starting => {
+                retry_after = Some(startup::RETRY_AFTER_SECS);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "server is starting".to_string(),
+                )
+            }
+            AppError::RangeNotSatisfiable(len) => {
+                content_range = Some(format!("bytes */{}", len));
+                (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    format!("the range starts past the end of the {} byte export", len),
+                )
+            }
+            AppError::ShuttingDown => {
+                retry_after = Some(draining::RETRY_AFTER_SECS);
+                close = true;
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "server is shutting down".to_string(),
+                )
+            }
+            AppError::Timeout => (
+                StatusCode::GATEWAY_TIMEOUT,
+                "database query timed out".to_string(),
+            ),
+            AppError::RequestTimeout(secs) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("request took longer than {} seconds", secs),
+            ),
+            AppError::ChaosFailure => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failure injected by chaos mode (CHAOS_ERROR_RATE)".to_string(),
+            ),
+            AppError::ReadOnly => {
+                error!("write refused, the database is read-only, check the permissions of the database file and its directory and how it's mounted");
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "database is read-only".to_string(),
+                )
+            }
+            AppError::Database(e) => internal("database error", e),
+            AppError::Io(e) => internal("file error", e),
+        };
+
+        let mut problem = json!({
+            "type": "about:blank",
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": message,
+        });
+        if let Ok(request) = REQUEST.try_with(Clone::clone) {
+            problem["instance"] = Value::String(request.path);
+            problem["request_id"] = Value::String(request.id);
+        }
+        if let Some(errors) = errors {
+            problem["errors"] = errors;
+        }
+
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(problem),
+        )
+            .into_response();
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        if let Some(content_range) = content_range {
+            response.headers_mut().insert(
+                header::CONTENT_RANGE,
+                HeaderValue::from_str(&content_range)
+                    .expect("a Content-Range is a valid header value"),
+            );
+        }
+        if close {
+            response
+                .headers_mut()
+                .insert(header::CONNECTION, HeaderValue::from_static("close"));
+        }
+        response
+    }
+}
+
+// the status and detail of an error the client can't do anything about, it's logged against the
+// request id and its text only reaches the client with APP_ENV=development
+fn internal(kind: &str, e: impl Display) -> (StatusCode, String) {
+    let request = REQUEST.try_with(Clone::clone).ok();
+    let request_id = request.as_ref().map_or("-", |request| request.id.as_str());
+    error!(request_id, "{}: {}", kind, e);
+    let message = match request {
+        Some(request) if request.verbose => format!("{}: {}", kind, e),
+        _ => format!("{}, quote the request_id when reporting it", kind),
+    };
+    (StatusCode::INTERNAL_SERVER_ERROR, message)
+}
+
+// middleware function which makes the request's path and id available to any problem document
+// built while it's handled and returns the id in X-Request-Id, it's the outermost layer so errors
+// from the other middleware get them too
+pub async fn with_request_context(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_usable_id(id))
+        .map_or_else(new_request_id, str::to_string);
+    let context = RequestContext {
+        path: request.uri().path().to_string(),
+        id: id.clone(),
+        verbose: config.app_env == AppEnv::Development,
+    };
+
+    let mut response = REQUEST.scope(context, next.run(request)).await;
+    if let Ok(id) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    }
+    response
+}
+
+// a client's request id is kept if it's short and made of characters safe to log
+fn is_usable_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+// a new id for a request that didn't bring one, e.g. "68f0a1b2-1f"
+fn new_request_id() -> String {
+    let count = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:x}", *STARTED, count)
+}
@@ -0,0 +1,84 @@
+// etag.rs
+// entity tags for single records and If-Match optimistic concurrency on mutations, and the
+// hasher the ranged export tags its content with
+// a record's ETag is a hash of its JSON form, so it changes whenever the record does without
+// the table needing a version column, a mutation carrying If-Match only goes ahead when the
+// record's current ETag is listed, otherwise it fails with 412, with REQUIRE_IF_MATCH a
+// mutation without the header fails with 428
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::TestRecord;
+use axum::http::{header, HeaderMap, HeaderValue};
+
+// FNV-1a parameters, a small stable hash is all a tag needs
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+// the strong ETag of a record, quoted as it appears in headers, e.g. "\"3f2a9c0d41b7e655\""
+pub fn of(record: &TestRecord) -> String {
+    let json = serde_json::to_vec(record).expect("a record always serializes to JSON");
+    let mut hasher = Hasher::new();
+    hasher.update(&json);
+    hasher.tag()
+}
+
+// an ETag built up a piece at a time, for content too large to hash in one go
+pub struct Hasher(u64);
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self(FNV_OFFSET_BASIS)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+        });
+    }
+
+    // the tag of everything hashed so far, quoted as it appears in headers
+    pub fn tag(&self) -> String {
+        format!("\"{:016x}\"", self.0)
+    }
+}
+
+// an ETag header for a record
+pub fn header(record: &TestRecord) -> (header::HeaderName, HeaderValue) {
+    let value = HeaderValue::from_str(&of(record)).expect("an ETag is always a valid header value");
+    (header::ETAG, value)
+}
+
+// reject a mutation sent without If-Match when REQUIRE_IF_MATCH is on, done before any
+// database work
+pub fn require(config: &Config, headers: &HeaderMap) -> Result<(), AppError> {
+    if config.require_if_match && !headers.contains_key(header::IF_MATCH) {
+        return Err(AppError::PreconditionRequired);
+    }
+    Ok(())
+}
+
+// check If-Match against the record as it is now, None when it doesn't exist
+// "*" matches any existing record, weak tags never match as If-Match uses strong comparison
+pub fn check(headers: &HeaderMap, current: Option<&TestRecord>) -> Result<(), AppError> {
+    let Some(if_match) = headers.get(header::IF_MATCH) else {
+        return Ok(());
+    };
+    let if_match = if_match.to_str().unwrap_or_default();
+
+    let matched = match current {
+        Some(_) if if_match.trim() == "*" => true,
+        Some(record) => {
+            let current = of(record);
+            if_match.split(',').any(|tag| tag.trim() == current)
+        }
+        None => false,
+    };
+    if matched {
+        Ok(())
+    } else {
+        Err(AppError::PreconditionFailed(
+            "the record has changed since it was read, If-Match doesn't match its ETag".to_string(),
+        ))
+    }
+}
@@ -0,0 +1,174 @@
+// extract.rs
+// request extractors shared by the handlers
+// Query, Path and Json stand in for axum's own, whose rejections are plain text, so a request
+// they can't make sense of gets a problem document like every other error
+
+use crate::error::AppError;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use axum_macros::{FromRequest, FromRequestParts};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// a query string like axum's Query
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(AppError))]
+pub struct Query<T>(pub T);
+
+// path parameters like axum's Path
+#[derive(Debug, FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(AppError))]
+pub struct Path<T>(pub T);
+
+// a JSON body like axum's Json, and like it a JSON response when returned
+#[derive(Debug, FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+// a JSON body like axum's Json, except that an empty (or whitespace only) body is rejected with
+// a 400 with the detail "request body is required" instead of a deserialization error
+#[derive(Debug)]
+pub struct RequiredJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for RequiredJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = is_json(request.headers());
+        let body = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| AppError::from(rejection).into_response())?;
+
+        if body.iter().all(u8::is_ascii_whitespace) {
+            return Err(
+                AppError::BadRequest("request body is required".to_string()).into_response()
+            );
+        }
+        if !is_json {
+            return Err(AppError::UnsupportedMediaType(
+                "expected Content-Type application/json".to_string(),
+            )
+            .into_response());
+        }
+
+        axum::Json::from_bytes(&body)
+            .map(|axum::Json(value)| RequiredJson(value))
+            .map_err(|rejection| AppError::from(rejection).into_response())
+    }
+}
+
+// check for application/json or a +json media type, ignoring parameters such as charset
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .is_some_and(|value| value == "application/json" || value.ends_with("+json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::StatusCode,
+        routing::{get, post},
+        Router,
+    };
+    use serde::Deserialize;
+    use tower::ServiceExt;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Params {
+        id: i32,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Record {
+        date: chrono::NaiveDate,
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/query", get(|_: Query<Params>| async {}))
+            .route("/path/{id}", get(|_: Path<i32>| async {}))
+            .route("/json", post(|_: Json<Record>| async {}))
+            .route("/required", post(|_: RequiredJson<Record>| async {}))
+    }
+
+    // the status and Content-Type of the answer to a request
+    async fn answer(request: Request) -> (StatusCode, String) {
+        let response = app().oneshot(request).await.expect("a response");
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        (response.status(), content_type)
+    }
+
+    fn get_request(uri: &str) -> Request {
+        Request::builder()
+            .uri(uri)
+            .body(Body::empty())
+            .expect("a valid request")
+    }
+
+    fn json_request(uri: &str, body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .expect("a valid request")
+    }
+
+    #[tokio::test]
+    async fn rejections_are_problem_documents() {
+        let problem = (
+            StatusCode::BAD_REQUEST,
+            "application/problem+json".to_string(),
+        );
+        assert_eq!(answer(get_request("/query?id=abc")).await, problem);
+        assert_eq!(answer(get_request("/query?id=1&bogus=1")).await, problem);
+        assert_eq!(answer(get_request("/path/abc")).await, problem);
+        let bad_date = r#"{"date": "2023-13-45"}"#;
+        assert_eq!(answer(json_request("/json", bad_date)).await, problem);
+        assert_eq!(answer(json_request("/required", bad_date)).await, problem);
+    }
+
+    #[tokio::test]
+    async fn a_json_body_without_its_content_type_is_a_415_problem() {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/json")
+            .body(Body::from(r#"{"date": "2023-01-25"}"#))
+            .expect("a valid request");
+        assert_eq!(
+            answer(request).await,
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "application/problem+json".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// filter.rs
+// the ?filter= mini language of /database_read, conditions separated by ";" such as
+// "message~hello;id>5", each is a column of the test table, an operator and a value
+// operators are "=", ">", "<" and "~" (the text contains the value, LIKE), conditions are ANDed
+// column names are only taken from the table registry in tables.rs and values are always bound,
+// never written into the SQL, anything outside the grammar is rejected with a 400
+
+use crate::error::AppError;
+use crate::tables::{ColumnKind, TABLES};
+use sqlx::query::Query as SqlQuery;
+use sqlx::sqlite::SqliteArguments;
+use sqlx::Sqlite;
+
+// most conditions accepted in one filter
+const MAX_CONDITIONS: usize = 10;
+
+// the comparison a condition makes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equal,
+    Greater,
+    Less,
+    Contains,
+}
+
+impl Operator {
+    const ALL: [(char, Operator); 4] = [
+        ('=', Operator::Equal),
+        ('>', Operator::Greater),
+        ('<', Operator::Less),
+        ('~', Operator::Contains),
+    ];
+
+    fn sql(self) -> &'static str {
+        match self {
+            Operator::Equal => "=",
+            Operator::Greater => ">",
+            Operator::Less => "<",
+            Operator::Contains => "LIKE",
+        }
+    }
+}
+
+// a value to bind, typed by the column it's compared with
+#[derive(Debug, Clone)]
+enum FilterValue {
+    Integer(i64),
+    Text(String),
+}
+
+// struct to hold one parsed condition, the column name comes from the registry
+#[derive(Debug, Clone)]
+struct Condition {
+    column: &'static str,
+    operator: Operator,
+    value: FilterValue,
+}
+
+// struct to hold a parsed filter, an empty one matches every record
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    conditions: Vec<Condition>,
+}
+
+impl Filter {
+    // parse the value of ?filter=, a missing parameter is an empty filter
+    pub fn parse(input: Option<&str>) -> Result<Self, AppError> {
+        let Some(input) = input else {
+            return Ok(Self::default());
+        };
+        let conditions = input
+            .split(';')
+            .map(parse_condition)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.len() > MAX_CONDITIONS {
+            return Err(invalid(format!(
+                "at most {} conditions are allowed",
+                MAX_CONDITIONS
+            )));
+        }
+        Ok(Self { conditions })
+    }
+
+    // number of placeholders the WHERE clause uses, later parameters are numbered after them
+    pub fn placeholders(&self) -> usize {
+        self.conditions.len()
+    }
+
+    // " WHERE ..." with numbered placeholders $1..$n, or nothing for an empty filter
+    pub fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            return String::new();
+        }
+        let conditions = self
+            .conditions
+            .iter()
+            .enumerate()
+            .map(|(index, condition)| {
+                let escape = match condition.operator {
+                    Operator::Contains => " ESCAPE '\\'",
+                    _ => "",
+                };
+                format!(
+                    "{} {} ${}{}",
+                    condition.column,
+                    condition.operator.sql(),
+                    index + 1,
+                    escape
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+
+    // bind the values in placeholder order
+    pub fn bind<'q>(
+        &self,
+        mut query: SqlQuery<'q, Sqlite, SqliteArguments<'q>>,
+    ) -> SqlQuery<'q, Sqlite, SqliteArguments<'q>> {
+        for condition in &self.conditions {
+            query = match (&condition.value, condition.operator) {
+                (FilterValue::Integer(value), _) => query.bind(*value),
+                (FilterValue::Text(value), Operator::Contains) => {
+                    query.bind(format!("%{}%", escape_like(value)))
+                }
+                (FilterValue::Text(value), _) => query.bind(value.clone()),
+            };
+        }
+        query
+    }
+}
+
+// parse a single "column<operator>value" condition against the test table's columns
+fn parse_condition(input: &str) -> Result<Condition, AppError> {
+    let (index, operator) = input
+        .char_indices()
+        .find_map(|(index, c)| {
+            Operator::ALL
+                .iter()
+                .find(|(symbol, _)| *symbol == c)
+                .map(|(_, operator)| (index, *operator))
+        })
+        .ok_or_else(|| {
+            invalid(format!(
+                "{:?} has no operator, expected one of =, >, < or ~",
+                input
+            ))
+        })?;
+    let name = input[..index].trim();
+    let value = &input[index + 1..];
+
+    let column = TABLES
+        .iter()
+        .find(|table| table.name == "test")
+        .and_then(|table| table.columns.iter().find(|column| column.name == name))
+        .ok_or_else(|| {
+            invalid(format!(
+                "{:?} is not a column that can be filtered on",
+                name
+            ))
+        })?;
+
+    if operator == Operator::Contains && column.kind == ColumnKind::Integer {
+        return Err(invalid(format!(
+            "~ only applies to text, not {}",
+            column.name
+        )));
+    }
+
+    let value = match column.kind {
+        ColumnKind::Integer => {
+            let value = value.trim().parse().map_err(|_| {
+                invalid(format!("{} must be compared with an integer", column.name))
+            })?;
+            FilterValue::Integer(value)
+        }
+        // dates compare as text, so a partial date such as date>2023 still works
+        ColumnKind::Text | ColumnKind::Date if value.is_empty() => {
+            return Err(invalid(format!(
+                "{} must be compared with a non-empty value",
+                column.name
+            )))
+        }
+        ColumnKind::Text | ColumnKind::Date => FilterValue::Text(value.to_string()),
+    };
+
+    Ok(Condition {
+        column: column.name,
+        operator,
+        value,
+    })
+}
+
+// escape LIKE's wildcards so "~" matches the value literally, to be used with ESCAPE '\'
+pub fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::BadRequest(format!("invalid filter: {}", message))
+}
@@ -0,0 +1,20 @@
+// flag.rs
+// shared parsing for boolean query parameters, so every route accepts the same spellings
+// and rejects anything else with a 400 naming the parameter
+
+use crate::error::AppError;
+
+// parse an optional flag, true/false, 1/0 or yes/no in any case, a missing flag is false
+pub fn parse(name: &str, value: Option<&str>) -> Result<bool, AppError> {
+    let Some(value) = value else {
+        return Ok(false);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(AppError::BadRequest(format!(
+            "invalid value for {}: {:?}, expected true/false, 1/0 or yes/no",
+            name, value
+        ))),
+    }
+}
@@ -0,0 +1,95 @@
+// http_server.rs
+// serves the application with hyper-util's auto connection builder, the one axum::serve uses,
+// so hyper's connection settings can be given, which axum::serve doesn't expose
+// a request's headers must arrive within HEADER_READ_TIMEOUT_SECS of its first byte, enforced by
+// hyper, which stops slow-loris style clients trickling them in, and HTTP_KEEP_ALIVE=false closes
+// each connection after its response, the builder answers HTTP/2 as well when hyper-util's http2
+// feature is on
+// these complement the per-request limits in timeout.rs, which only start once a request is routed
+
+use crate::config::Config;
+use axum::{extract::ConnectInfo, http::Request, serve::Listener, Router};
+use hyper::{body::Incoming, service::service_fn};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use std::pin::pin;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use tower_service::Service;
+use tracing::debug;
+
+// the connection settings, header_read is None when HEADER_READ_TIMEOUT_SECS is 0
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub header_read: Option<Duration>,
+    pub keep_alive: bool,
+}
+
+impl Settings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            header_read: match config.header_read_timeout_secs {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            keep_alive: config.http_keep_alive,
+        }
+    }
+}
+
+// accept connections and serve the application on them until shutdown is cancelled, then let
+// the requests in flight finish and close every connection, as axum::serve's graceful shutdown
+// does, each request carries ConnectInfo with the peer address
+pub async fn serve<L>(mut listener: L, app: Router, settings: Settings, shutdown: CancellationToken)
+where
+    L: Listener,
+    L::Addr: Clone + Send + Sync + 'static,
+{
+    let mut builder = Builder::new(TokioExecutor::new());
+    let mut http1 = builder.http1();
+    http1
+        .timer(TokioTimer::new())
+        .keep_alive(settings.keep_alive);
+    if let Some(timeout) = settings.header_read {
+        http1.header_read_timeout(timeout);
+    }
+    let connections = TaskTracker::new();
+
+    loop {
+        let (io, addr) = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown.cancelled() => break,
+        };
+
+        let app = app.clone();
+        let service = service_fn(move |mut request: Request<Incoming>| {
+            request.extensions_mut().insert(ConnectInfo(addr.clone()));
+            app.clone().call(request)
+        });
+        let builder = builder.clone();
+        let shutdown = shutdown.clone();
+        connections.spawn(async move {
+            let mut connection =
+                pin!(builder.serve_connection_with_upgrades(TokioIo::new(io), service));
+            let mut closing = false;
+            loop {
+                tokio::select! {
+                    result = connection.as_mut() => {
+                        if let Err(e) = result {
+                            debug!("connection closed: {}", e);
+                        }
+                        break;
+                    }
+                    _ = shutdown.cancelled(), if !closing => {
+                        connection.as_mut().graceful_shutdown();
+                        closing = true;
+                    }
+                }
+            }
+        });
+    }
+
+    connections.close();
+    connections.wait().await;
+}
@@ -0,0 +1,59 @@
+// id_format.rs
+// controls how record ids are written in JSON responses, as numbers by default or as strings
+// when ID_AS_STRING is set, for clients which lose precision on large integers
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Serializer;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// ids are numeric until the config says otherwise
+static ID_AS_STRING: AtomicBool = AtomicBool::new(false);
+
+// switch string ids on or off, called once at startup from the config
+pub fn set_id_as_string(as_string: bool) {
+    ID_AS_STRING.store(as_string, Ordering::Relaxed);
+}
+
+// serializer for id fields, used with #[serde(serialize_with = "id_format::serialize")]
+pub fn serialize<S: Serializer>(id: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    if ID_AS_STRING.load(Ordering::Relaxed) {
+        serializer.collect_str(id)
+    } else {
+        serializer.serialize_i32(*id)
+    }
+}
+
+// deserializer for id fields which accepts either form, so ids written as strings can be
+// sent back (and read back from the audit log's stored records) whatever the setting
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    struct IdVisitor;
+
+    impl Visitor<'_> for IdVisitor {
+        type Value = i32;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an integer id, as a number or a string")
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<i32, E> {
+            i32::try_from(value).map_err(|_| E::custom(format!("id out of range: {}", value)))
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<i32, E> {
+            i32::try_from(value).map_err(|_| E::custom(format!("id out of range: {}", value)))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<i32, E> {
+            value
+                .parse()
+                .map_err(|_| E::custom(format!("invalid id: {:?}", value)))
+        }
+    }
+
+    deserializer.deserialize_any(IdVisitor)
+}
+
+// wrapper for ids returned outside a record, e.g. lists of ids in a batch response
+#[derive(serde::Serialize, Debug)]
+pub struct Id(#[serde(serialize_with = "serialize")] pub i32);
@@ -0,0 +1,222 @@
+// import.rs
+// bulk import of complete records, the whole payload is validated before the database is
+// touched, so an import either stores every record in one transaction or reports every
+// problem at once with a 422 and stores nothing
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Json;
+use crate::{audit, compress, outbox, retry, TestRecord};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{info, instrument, warn};
+
+// longest message accepted by an import, in characters
+const MAX_MESSAGE_CHARS: usize = 1000;
+
+// format every imported date must follow
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// handler function for the route which imports a JSON array of records
+// each record needs an id, a YYYY-MM-DD date and a non-empty message
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_import", method = "POST", count = payload.len()))]
+pub async fn import_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(payload): Json<Vec<Value>>,
+) -> Result<impl IntoResponse, AppError> {
+    if payload.len() > config.max_batch_size {
+        return Err(AppError::BadRequest(format!(
+            "batch size {} exceeds the maximum of {}",
+            payload.len(),
+            config.max_batch_size
+        )));
+    }
+
+    let records = validate(&payload)?;
+
+    let (pool, config, records) = (&pool, &config, &records);
+    retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        for record in records {
+            let message = compress::store(config, &record.message);
+            sqlx::query(
+                "INSERT INTO test (id, date, message, message_zstd) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(record.id)
+            .bind(record.date)
+            .bind(message.text)
+            .bind(message.zstd)
+            .execute(&mut *tx)
+            .await?;
+            audit::record_change(&mut tx, record.id, audit::CREATE, None).await?;
+            outbox::enqueue(&mut tx, config, outbox::RECORD_CREATED, record).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    })
+    .await?;
+    info!("imported {} records", records.len());
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "imported": records.len() })),
+    ))
+}
+
+// one problem with one record of a bulk request, index is the record's position in the array
+// and field the member at fault, or null when it's the record as a whole
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    pub index: usize,
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(index: usize, field: Option<&'static str>, message: impl Into<String>) -> Self {
+        Self {
+            index,
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+// fail a bulk request with a 422 listing every problem found, nothing has been stored
+pub fn reject(what: &str, errors: Vec<FieldError>) -> AppError {
+    let records = errors
+        .iter()
+        .map(|error| error.index)
+        .collect::<HashSet<_>>()
+        .len();
+    warn!(
+        "rejected {}, {} problems in {} records",
+        what,
+        errors.len(),
+        records
+    );
+    AppError::Unprocessable(
+        format!("{} contains invalid records, nothing was stored", what),
+        json!(errors),
+    )
+}
+
+// check every record in the payload, returning them all when valid and otherwise every
+// problem found, in the order of the records they belong to
+fn validate(payload: &[Value]) -> Result<Vec<TestRecord>, AppError> {
+    let mut records = Vec::with_capacity(payload.len());
+    let mut errors = Vec::new();
+    let mut seen_ids = HashSet::new();
+
+    for (index, value) in payload.iter().enumerate() {
+        match validate_record(index, value, &mut errors) {
+            Some(record) if !seen_ids.insert(record.id) => errors.push(FieldError::new(
+                index,
+                Some("id"),
+                format!("id {} appears more than once in the import", record.id),
+            )),
+            Some(record) => records.push(record),
+            None => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(records)
+    } else {
+        Err(reject("import", errors))
+    }
+}
+
+// check a single record, noting every problem rather than stopping at the first
+pub fn validate_record(
+    index: usize,
+    value: &Value,
+    errors: &mut Vec<FieldError>,
+) -> Option<TestRecord> {
+    let Some(fields) = value.as_object() else {
+        errors.push(FieldError::new(index, None, "record must be a JSON object"));
+        return None;
+    };
+    let found = errors.len();
+
+    // ids may be numbers or numeric strings, matching what ID_AS_STRING writes
+    let id = match fields.get("id") {
+        None | Some(Value::Null) => {
+            errors.push(FieldError::new(index, Some("id"), "id is required"));
+            None
+        }
+        Some(value) => {
+            let id = match value {
+                Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            };
+            if id.is_none() {
+                errors.push(FieldError::new(index, Some("id"), "id must be an integer"));
+            }
+            id
+        }
+    };
+
+    let date = required_string(index, fields.get("date"), "date", errors).and_then(|date| {
+        let parsed = NaiveDate::parse_from_str(&date, DATE_FORMAT).ok();
+        if parsed.is_none() {
+            errors.push(FieldError::new(
+                index,
+                Some("date"),
+                format!("date must be a YYYY-MM-DD date, got {:?}", date),
+            ));
+        }
+        parsed
+    });
+
+    let message = required_string(index, fields.get("message"), "message", errors);
+    if let Some(message) = &message {
+        let chars = message.chars().count();
+        if chars > MAX_MESSAGE_CHARS {
+            errors.push(FieldError::new(
+                index,
+                Some("message"),
+                format!(
+                    "message is {} characters, the maximum is {}",
+                    chars, MAX_MESSAGE_CHARS
+                ),
+            ));
+        }
+    }
+
+    match (id, date, message) {
+        (Some(id), Some(date), Some(message)) if errors.len() == found => Some(TestRecord {
+            id,
+            date,
+            message,
+            updated_at: None,
+            created_at: None,
+        }),
+        _ => None,
+    }
+}
+
+// read a field that must be a non-empty string, noting why when it isn't
+fn required_string(
+    index: usize,
+    value: Option<&Value>,
+    name: &'static str,
+    errors: &mut Vec<FieldError>,
+) -> Option<String> {
+    let message = match value {
+        None | Some(Value::Null) => format!("{} is required", name),
+        Some(Value::String(s)) if s.trim().is_empty() => format!("{} must not be empty", name),
+        Some(Value::String(s)) => return Some(s.clone()),
+        Some(_) => format!("{} must be a string", name),
+    };
+    errors.push(FieldError::new(index, Some(name), message));
+    None
+}
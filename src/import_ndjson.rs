@@ -0,0 +1,254 @@
+// import_ndjson.rs
+// streaming import of newline-delimited JSON (NDJSON) records for files too large for the
+// array import, the body is read a chunk at a time and the records are stored every
+// IMPORT_BATCH_SIZE lines, each batch in its own transaction, so the file is never held in memory
+// each line is checked like a record of /database_import, a line that isn't a valid record or
+// whose id is already taken is skipped and counted, with ?strict=true it stops the import
+// instead, either way the batches already committed are kept
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Query;
+use crate::import::{validate_record, FieldError};
+use crate::{audit, compress, flag, outbox, queries, retry, updated_at_now, TestRecord};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
+};
+use futures::stream::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use tracing::{debug, info, instrument, warn};
+
+// media types accepted for the body
+const NDJSON_TYPES: &[&str] = &["application/x-ndjson", "application/jsonl"];
+
+// longest line accepted, in bytes, a longer one is skipped without being buffered
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+// struct to hold the query parameters of the NDJSON import
+#[derive(Deserialize, Debug)]
+pub struct NdjsonParams {
+    strict: Option<String>,
+}
+
+// how far the import has got, lines are numbered from 1
+#[derive(Default)]
+struct Progress {
+    line: usize,
+    inserted: usize,
+    errors: usize,
+}
+
+// handler function for the route which imports NDJSON records as they arrive
+// answers with the number of records inserted and the number of lines skipped
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_import_ndjson", method = "POST"))]
+pub async fn import_ndjson(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<NdjsonParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<impl IntoResponse, AppError> {
+    let strict = flag::parse("strict", params.strict.as_deref())?;
+    if !is_ndjson(&headers) {
+        return Err(AppError::UnsupportedMediaType(format!(
+            "expected Content-Type {}",
+            NDJSON_TYPES.join(" or ")
+        )));
+    }
+
+    let mut chunks = body.into_data_stream();
+    let mut buffer = Vec::new();
+    // set while the rest of an over-long line is being thrown away
+    let mut discarding = false;
+    let mut batch = Vec::with_capacity(config.import_batch_size);
+    let mut progress = Progress::default();
+
+    loop {
+        let chunk = chunks
+            .next()
+            .await
+            .transpose()
+            .map_err(|e| AppError::BadRequest(format!("could not read the import: {}", e)))?;
+        let finished = chunk.is_none();
+        if let Some(chunk) = chunk {
+            buffer.extend_from_slice(&chunk);
+        }
+
+        let mut start = 0;
+        while let Some(end) = buffer[start..].iter().position(|&b| b == b'\n') {
+            let line = &buffer[start..start + end];
+            start += end + 1;
+            if std::mem::take(&mut discarding) {
+                continue;
+            }
+            progress.line += 1;
+            parse_line(progress.line, line, strict, &mut batch, &mut progress)?;
+            if batch.len() >= config.import_batch_size {
+                store_batch(&pool, &config, &batch, strict, &mut progress).await?;
+                batch.clear();
+            }
+        }
+        buffer.drain(..start);
+
+        if finished {
+            // the last line needn't end with a newline
+            if !buffer.is_empty() && !discarding {
+                progress.line += 1;
+                parse_line(progress.line, &buffer, strict, &mut batch, &mut progress)?;
+            }
+            break;
+        }
+        if buffer.len() > MAX_LINE_BYTES && !discarding {
+            progress.line += 1;
+            let error = FieldError::new(
+                progress.line,
+                None,
+                format!("line is longer than {} bytes", MAX_LINE_BYTES),
+            );
+            skip(vec![error], strict, &mut progress)?;
+            buffer.clear();
+            discarding = true;
+        } else if discarding {
+            buffer.clear();
+        }
+    }
+    if !batch.is_empty() {
+        store_batch(&pool, &config, &batch, strict, &mut progress).await?;
+    }
+    info!(
+        "imported {} records from {} lines, skipped {}",
+        progress.inserted, progress.line, progress.errors
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "inserted": progress.inserted, "errors": progress.errors })),
+    ))
+}
+
+// check a line and add its record to the batch, a blank line is ignored
+fn parse_line(
+    line_number: usize,
+    line: &[u8],
+    strict: bool,
+    batch: &mut Vec<(usize, TestRecord)>,
+    progress: &mut Progress,
+) -> Result<(), AppError> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+    let value: Value = match serde_json::from_slice(line) {
+        Ok(value) => value,
+        Err(e) => {
+            let error =
+                FieldError::new(line_number, None, format!("line is not valid JSON: {}", e));
+            return skip(vec![error], strict, progress);
+        }
+    };
+    let mut errors = Vec::new();
+    match validate_record(line_number, &value, &mut errors) {
+        Some(record) => {
+            batch.push((line_number, record));
+            Ok(())
+        }
+        None => skip(errors, strict, progress),
+    }
+}
+
+// count a line that can't be imported, or with strict stop the import with a 422 listing
+// its problems
+fn skip(errors: Vec<FieldError>, strict: bool, progress: &mut Progress) -> Result<(), AppError> {
+    let line = errors.first().map_or(progress.line, |error| error.index);
+    if strict {
+        warn!(
+            "stopped import at line {}, {} records were stored before it",
+            line, progress.inserted
+        );
+        return Err(AppError::Unprocessable(
+            format!(
+                "line {} of the import is invalid, the {} records stored before it were kept",
+                line, progress.inserted
+            ),
+            json!(errors),
+        ));
+    }
+    debug!("skipping line {} of the import: {:?}", line, errors);
+    progress.errors += 1;
+    Ok(())
+}
+
+// store a batch of records in one transaction with their audit entries and outbox events
+// a record whose id is taken is skipped, with strict the records before it are committed and
+// the import stops
+async fn store_batch(
+    pool: &SqlitePool,
+    config: &Config,
+    batch: &[(usize, TestRecord)],
+    strict: bool,
+    progress: &mut Progress,
+) -> Result<(), AppError> {
+    let (inserted, duplicates) = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let mut inserted = 0;
+        let mut duplicates = Vec::new();
+        for (line, record) in batch {
+            let message = compress::store(config, &record.message);
+            let result = sqlx::query(queries::INSERT_RECORD)
+                .bind(record.id)
+                .bind(record.date)
+                .bind(message.text)
+                .bind(message.zstd)
+                .bind(updated_at_now())
+                .execute(&mut *tx)
+                .await;
+            match result.map_err(AppError::from) {
+                Ok(_) => {
+                    audit::record_change(&mut tx, record.id, audit::CREATE, None).await?;
+                    outbox::enqueue(&mut tx, config, outbox::RECORD_CREATED, record).await?;
+                    inserted += 1;
+                }
+                Err(AppError::Conflict(_)) => {
+                    duplicates.push(FieldError::new(
+                        *line,
+                        Some("id"),
+                        format!("a record with id {} already exists", record.id),
+                    ));
+                    if strict {
+                        break;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        tx.commit().await?;
+        Ok((inserted, duplicates))
+    })
+    .await?;
+    debug!("stored a batch of {} records", inserted);
+
+    progress.inserted += inserted;
+    for duplicate in duplicates {
+        skip(vec![duplicate], strict, progress)?;
+    }
+    Ok(())
+}
+
+// check for one of the NDJSON media types, ignoring parameters such as charset
+fn is_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|value| {
+            NDJSON_TYPES
+                .iter()
+                .any(|ndjson| value.trim().eq_ignore_ascii_case(ndjson))
+        })
+}
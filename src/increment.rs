@@ -0,0 +1,73 @@
+// increment.rs
+// counters kept in a record's message, POST /database_increment/{id} adds one to a message that
+// holds a whole number and answers with the new value
+// the addition is made by SQLite in the UPDATE itself, inside a transaction with the check and
+// the audit entry, so concurrent increments can't read the same value and lose one
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::id_format::Id;
+use crate::{audit, find_record, retry, server_timing, updated_at_now, TestRecord};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+// handler function for the route which increments a record's numeric message
+// a missing record is a 404, a message that isn't a whole number, or is the largest one, a 422
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_increment/{id}", method = "POST", id = id))]
+pub async fn increment(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let pool = &pool;
+    let value = retry::on_busy(&config, || async move {
+        let mut tx = pool.begin().await?;
+        let before = find_record(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("no record with id {}", id)))?;
+        check_numeric(&before)?;
+
+        // a compressed message's text column is empty, so its value is bound in instead, the
+        // result is short enough to always be stored as plain text
+        let value = sqlx::query_scalar::<_, i64>(
+            "UPDATE test SET \
+             message = CAST(CASE WHEN message_zstd IS NULL THEN message ELSE $1 END AS INTEGER) + 1, \
+             message_zstd = NULL, updated_at = $2 \
+             WHERE id = $3 RETURNING CAST(message AS INTEGER)",
+        )
+        .bind(&before.message)
+        .bind(updated_at_now())
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record_change(&mut tx, id, audit::UPDATE, Some(&before)).await?;
+        tx.commit().await?;
+        Ok(value)
+    })
+    .await?;
+    info!("record incremented to {}", value);
+
+    Ok((
+        StatusCode::OK,
+        server_timing::json(json!({ "id": Id(id), "value": value })),
+    ))
+}
+
+// check the message is a whole number that can be incremented, as Rust and SQLite both read it,
+// SQLite's CAST would turn "12abc" into 12 and "abc" into 0 rather than fail
+fn check_numeric(record: &TestRecord) -> Result<(), AppError> {
+    let problem = match record.message.parse::<i64>() {
+        Ok(i64::MAX) => "message is already the largest value a counter can hold",
+        Ok(_) => return Ok(()),
+        Err(_) => "message is not a whole number",
+    };
+    Err(AppError::Unprocessable(
+        format!("record {} can't be incremented", record.id),
+        json!([{ "field": "message", "message": problem }]),
+    ))
+}
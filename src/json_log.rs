@@ -0,0 +1,183 @@
+// json_log.rs
+// writes log lines as one JSON object each for LOG_FORMAT=json, with the time, level, target,
+// the event's fields and the fields of the spans it happened in, so a log collector can index
+// them without parsing the pretty format
+// this stands in for tracing-subscriber's own json feature, whose tracing-serde dependency isn't
+// in the registry this project builds from
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+// formats an event as {"timestamp", "level", "target", "fields", "spans"}
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+
+        // outermost span first, each with the fields JsonFields stored for it
+        let spans: Vec<Value> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut entry = span
+                    .extensions()
+                    .get::<FormattedFields<N>>()
+                    .and_then(|stored| serde_json::from_str::<Map<String, Value>>(stored).ok())
+                    .unwrap_or_default();
+                entry.insert("name".to_string(), span.name().into());
+                Value::Object(entry)
+            })
+            .collect();
+
+        let line = serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields,
+            "spans": spans,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
+
+// stores a span's fields as a JSON object, which JsonFormat reads back when the span's events
+// are written
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut map = Map::new();
+        fields.record(&mut JsonVisitor(&mut map));
+        write!(writer, "{}", Value::Object(map))
+    }
+
+    // fields recorded on a span after it's created are merged into its object, rather than
+    // appended after it as the default does
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut map: Map<String, Value> = serde_json::from_str(&current.fields).unwrap_or_default();
+        fields.record(&mut JsonVisitor(&mut map));
+        current.fields = Value::Object(map).to_string();
+        Ok(())
+    }
+}
+
+// collects fields into a JSON object, keeping numbers and booleans as they are
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing::info;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    // collects what the subscriber writes
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0
+                .lock()
+                .expect("the buffer lock")
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Captured;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn an_event_is_one_json_line_with_its_span_fields() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)
+            .with_writer(captured.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", route = "/database_read", id = 7);
+            let _entered = span.enter();
+            info!(rows = 2, cached = false, "read \"page\"");
+        });
+
+        let written = String::from_utf8(captured.0.lock().expect("the buffer lock").clone())
+            .expect("the log line is UTF-8");
+        assert_eq!(written.lines().count(), 1);
+        let line: Value = serde_json::from_str(&written).expect("the log line is JSON");
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "read \"page\"");
+        assert_eq!(line["fields"]["rows"], 2);
+        assert_eq!(line["fields"]["cached"], false);
+        assert_eq!(line["spans"][0]["name"], "request");
+        assert_eq!(line["spans"][0]["route"], "/database_read");
+        assert_eq!(line["spans"][0]["id"], 7);
+    }
+}
@@ -0,0 +1,130 @@
+// log_writer.rs
+// a non-blocking writer for the tracing subscriber, formatted log lines are handed to a
+// background thread over a bounded channel and written out there in batches, so a request
+// never waits on stdout, when the channel is full lines are dropped rather than blocking and
+// the number dropped is reported once the writer catches up
+// the guard returned with the writer must be held for the life of the process, dropping it
+// writes out everything still queued
+// this is tracing_appender::non_blocking and its WorkerGuard, down to the 128,000 line buffer,
+// kept here only because tracing-appender can't be built from the registry this project builds
+// from (its symlink dependency isn't there), once it can be the call in main is the only change
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tracing_subscriber::fmt::MakeWriter;
+
+// log lines the channel holds before new ones are dropped
+const BUFFERED_LINES: usize = 128_000;
+
+// what the background thread is sent
+enum Message {
+    Line(Vec<u8>),
+    Shutdown,
+}
+
+// the writer handed to the subscriber, cheap to clone, one per log event
+#[derive(Clone)]
+pub struct NonBlocking {
+    sender: SyncSender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+// flushes the queued lines and stops the background thread when dropped
+pub struct WorkerGuard {
+    sender: SyncSender<Message>,
+    worker: Option<JoinHandle<()>>,
+}
+
+// start the background thread writing to output, failing if the thread can't be spawned
+pub fn non_blocking<W: Write + Send + 'static>(
+    output: W,
+) -> io::Result<(NonBlocking, WorkerGuard)> {
+    let (sender, receiver) = mpsc::sync_channel(BUFFERED_LINES);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let worker = {
+        let dropped = dropped.clone();
+        thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || write_lines(receiver, output, dropped))?
+    };
+
+    Ok((
+        NonBlocking {
+            sender: sender.clone(),
+            dropped,
+        },
+        WorkerGuard {
+            sender,
+            worker: Some(worker),
+        },
+    ))
+}
+
+impl Write for NonBlocking {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.sender.try_send(Message::Line(buf.to_vec())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "the log writer has stopped",
+                ))
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for NonBlocking {
+    type Writer = NonBlocking;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        // a blocking send, the shutdown message must get through even when the channel is full
+        let _ = self.sender.send(Message::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// the background thread, writes lines as they arrive and flushes whenever the channel is empty
+fn write_lines<W: Write>(receiver: Receiver<Message>, output: W, dropped: Arc<AtomicU64>) {
+    let mut output = io::BufWriter::new(output);
+    let mut message = receiver.recv();
+    while let Ok(Message::Line(line)) = message {
+        let _ = output.write_all(&line);
+        message = match receiver.try_recv() {
+            Ok(next) => Ok(next),
+            Err(TryRecvError::Empty) => {
+                let lost = dropped.swap(0, Ordering::Relaxed);
+                if lost > 0 {
+                    let _ = writeln!(
+                        output,
+                        "log writer fell behind, {} log lines were dropped",
+                        lost
+                    );
+                }
+                let _ = output.flush();
+                receiver.recv()
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+    }
+    let _ = output.flush();
+}
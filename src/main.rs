@@ -1,76 +1,668 @@
 // main.rs
 // This is a bare-bones starter for an API using the Axum web framework.
 // Database connectivity is included, the sqlx crate.
-// it has four routes: "/" - root route and "/health_check" - to return API status information
-// "/database_crate" - adds data to the id, date, and message fields from URL parameters
-// "/database_read" - returns all data entered into the database
+// its routes are:
+// "/" - root route, lists the routes below
+// "/health_check" - returns API status information
+// "/readyz" - readiness probe, 503 when the database is unreachable or disk space is low
+// "/database_create" - adds a record from its id, date and message
+// "/database_read" - returns the records a page at a time, optionally filtered, as JSON or XML
+// "/database_search" - returns a single record by the id in the query string, as JSON or XML
+// "/database_clone/{id}" - copies a record under a new id
 // "/database_update" - updates a single record by id
-// "/database_delete" = deletes a single record by id
+// "DELETE /database_delete" = deletes a single record by id, POST is kept as a deprecated alias
+// "/database_view" - returns a page of records as an HTML table with previous and next links
+// "/database/{id}" - returns a single record by id, 404 when it doesn't exist
+// "PATCH /database/{id}" - changes a record's date or message from a JSON Patch or a partial JSON body
+// "/database_first" and "/database_last" - return the records with the lowest and highest id
+// "/database_exists" - reports whether a record with the given id exists
+// "/database_random" - returns a random record, or n distinct random records
+// "/database_recent" - returns the n most recently created records, newest first
+// "/database_count" - returns the number of records, or of those matching a filter
+// "/database_by_date" - returns the number of records per date, optionally between from and to
+// "/database_autocomplete" - suggests distinct messages starting with a prefix
+// "/database_changes" - returns the records written since a time, oldest first, for incremental sync
+// "/database_dates" - returns the distinct dates records are stored against, in order
+// "/db/{table}/read", "/db/{table}/create" and "/db/{table}/{id}" - generic CRUD over the
+//     tables registered in tables.rs (test and notes), rows are JSON objects
+// "/database_schema" - describes the columns of the test table
+// "/database_stream" - streams all records as newline-delimited JSON
+// "/database_reserve" - reserves an id for a record whose data comes later, it lapses after a while
+// "/database_confirm/{id}" - fills in a reserved record, making it permanent
+// "/database_increment/{id}" - adds one to a record whose message is a whole number, a counter
+// "/database_update_batch" - updates the message of many records in one transaction (API key required)
+// "/database_bulk_delete" - deletes the records with the given ids in one transaction (API key required)
+// "/database_import" - stores a validated array of records in one transaction (API key required)
+// "/database_import_ndjson" - streams newline-delimited JSON records in, stored in batches (API key required)
+// "/admin" (with "/admin/page.js" and "/admin/page.css") - a browser page to list, create, update and delete records, asks for the API key
+// "/admin/undo" - reverses the most recent change to a record using the audit log (API key required)
+// "/admin/reload" - re-reads the configuration from the env file (API key required)
+// "/admin/config" - returns the configuration in effect with secrets redacted (API key required)
+// "/admin/migrations" - lists applied and pending schema migrations (API key required)
+// "/admin/slowest" - lists the slowest requests since startup with their database time (API key required)
+// "/admin/vacuum" - compacts the database file, reporting its size before and after (API key required)
 // there is a fallback route, which serves up a 404 Not Found, for routes that don't exist yet
+// each handler runs inside a tracing span recording its route, method and record id,
+// message content is deliberately left out of the spans
+
+// the json! listing every setting in Config::redacted outgrows the default macro recursion limit
+#![recursion_limit = "256"]
+
+// modules
+mod access_log;
+mod admin_page;
+mod allowlist;
+mod audit;
+mod auth;
+mod chaos;
+mod charset;
+mod compress;
+mod concurrency;
+mod config;
+mod decode;
+mod draining;
+mod envelope;
+mod error;
+mod etag;
+mod extract;
+mod filter;
+mod flag;
+mod http_server;
+mod id_format;
+mod import;
+mod import_ndjson;
+mod increment;
+mod json_log;
+mod log_writer;
+mod migrations;
+mod outbox;
+mod patch;
+mod pragmas;
+mod queries;
+mod range;
+mod readiness;
+mod redact;
+mod reservations;
+mod retry;
+mod server_timing;
+mod slowest;
+mod sql_log;
+mod sql_timeout;
+mod startup;
+mod statement_cache;
+mod stats;
+mod stream;
+mod tables;
+#[cfg(test)]
+mod test_support;
+mod timeout;
+mod trailing_slash;
+mod uri_limit;
+mod vacuum;
+mod view;
+mod write_queue;
+mod xml;
 
 // import dependencies
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Json},
-    routing::{get, post, put},
+    extract::{FromRef, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, map_response, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, options, patch, post, put, MethodRouter},
+    serve::ListenerExt,
     Router,
 };
-use color_eyre::eyre::Result;
+use axum_macros::FromRef;
+use chrono::{DateTime, NaiveDate, Utc};
+use color_eyre::eyre::{eyre, Result};
+use config::{AppEnv, Config, LogFormat, SharedConfig};
+use draining::ShuttingDown;
+use error::AppError;
+use extract::{Json, Path, Query, RequiredJson};
+use filter::Filter;
 use futures::future::pending;
+use readiness::HealthPool;
+use redact::RedactedMessage;
 use serde::{Deserialize, Serialize};
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
-use sqlx::FromRow;
+use serde_json::json;
+use slowest::SlowestRequests;
+use socket2::{SockRef, TcpKeepalive};
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions, SqliteRow,
+};
+use sqlx::{ConnectOptions, FromRow, Row};
+use startup::StartingUp;
+use stats::RequestStats;
+use std::fmt;
+use std::io::ErrorKind;
 use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::signal;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tower_http::set_header::SetResponseHeaderLayer;
 use tracing::subscriber::set_global_default;
-use tracing::{info, Level};
+use tracing::{debug, error, info, instrument, warn, Level};
 use tracing_subscriber::FmtSubscriber;
+use write_queue::WriteQueue;
+
+// number of records returned by /database_recent when n isn't given, and the most it returns
+const DEFAULT_RECENT: i64 = 10;
+const MAX_RECENT: i64 = 100;
+
+// number of suggestions returned by /database_autocomplete when limit isn't given, and the most
+const DEFAULT_AUTOCOMPLETE: i64 = 10;
+const MAX_AUTOCOMPLETE: i64 = 50;
+
+// most records returned by /database_random?n=
+const MAX_RANDOM: i64 = 100;
+
+// routes listed on the root page as (method, path, description), GET routes are rendered as links
+const ROOT_LINKS: &[(&str, &str, &str)] = &[
+    ("GET", "/", "this page, the root"),
+    ("GET", "/health_check", "current API status"),
+    ("GET", "/readyz", "database and disk space readiness"),
+    ("GET", "/admin", "manage records from the browser"),
+    (
+        "GET",
+        "/database_read",
+        "all records, paginated with limit and offset",
+    ),
+    (
+        "GET",
+        "/database_view",
+        "records as an HTML table, a page at a time",
+    ),
+    (
+        "GET",
+        "/database_recent",
+        "the most recently created records",
+    ),
+    ("GET", "/database_first", "the record with the lowest id"),
+    ("GET", "/database_last", "the record with the highest id"),
+    ("GET", "/database_random", "a randomly chosen record"),
+    (
+        "GET",
+        "/database_count",
+        "the number of records, optionally filtered",
+    ),
+    ("GET", "/database_by_date", "record counts per date"),
+    ("GET", "/database_dates", "the distinct record dates"),
+    (
+        "GET",
+        "/database_changes",
+        "records written since a time, for syncing",
+    ),
+    (
+        "GET",
+        "/database_schema",
+        "the columns of the records table",
+    ),
+    (
+        "GET",
+        "/database_stream",
+        "all records as newline-delimited JSON",
+    ),
+    ("GET", "/database_search?id=1", "a single record by id"),
+    (
+        "GET",
+        "/database_exists?id=1",
+        "whether a record id is taken",
+    ),
+    ("POST", "/database_create", "add a record"),
+    ("POST", "/database_clone/1", "copy a record under a new id"),
+    (
+        "POST",
+        "/database_increment/1",
+        "add one to a numeric message",
+    ),
+    ("PUT", "/database_update", "change a record's message"),
+    ("DELETE", "/database_delete", "remove a record"),
+];
+
+// header set on an unpaginated read cut short by READ_HARD_LIMIT
+static X_TRUNCATED: HeaderName = HeaderName::from_static("x-truncated");
+
+// header set on responses to routes kept only for backward compatibility
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+// connections the database pool opens at most
+const POOL_MAX_CONNECTIONS: u32 = 5;
+
+// permissions for the Unix domain socket, owner and group can connect
+#[cfg(unix)]
+const UNIX_SOCKET_MODE: u32 = 0o660;
 
 // struct to hold data read in from the test database
-#[derive(Deserialize, Serialize, Clone, Debug, FromRow)]
+#[derive(Deserialize, Serialize, Clone)]
 struct TestRecord {
+    #[serde(
+        serialize_with = "id_format::serialize",
+        deserialize_with = "id_format::deserialize"
+    )]
+    id: i32,
+    // a YYYY-MM-DD date, anything else is refused when the record is deserialized
+    date: NaiveDate,
+    message: String,
+    // set by the server on every write, a client can't supply it
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    updated_at: Option<String>,
+    // set by the server when the record is first stored and never written again, it's read back
+    // from audit before-images so an undone delete keeps it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+}
+
+// Debug is written by hand for the record types so the message is redacted in logs
+impl fmt::Debug for TestRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestRecord")
+            .field("id", &self.id)
+            .field("date", &self.date)
+            .field("message", &RedactedMessage(&self.message))
+            .field("updated_at", &self.updated_at)
+            .field("created_at", &self.created_at)
+            .finish()
+    }
+}
+
+// struct to hold the input for a new record, date and message are optional
+// and are filled in by the server when they're left out
+#[derive(Deserialize)]
+struct NewRecord {
     id: i32,
+    date: Option<NaiveDate>,
+    message: Option<String>,
+}
+
+// struct to hold the query parameters of the create route, on_conflict is fail (the default),
+// ignore or replace
+#[derive(Deserialize, Debug)]
+struct CreateParams {
+    on_conflict: Option<String>,
+}
+
+// what a create does when the id is already taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OnConflict {
+    // refuse with a 409
+    Fail,
+    // leave the existing record as it is
+    Ignore,
+    // overwrite the existing record
+    Replace,
+}
+
+impl OnConflict {
+    fn parse(value: Option<&str>) -> Result<Self, AppError> {
+        match value {
+            None | Some("fail") => Ok(OnConflict::Fail),
+            Some("ignore") => Ok(OnConflict::Ignore),
+            Some("replace") => Ok(OnConflict::Replace),
+            Some(value) => Err(AppError::BadRequest(format!(
+                "invalid value for on_conflict: {:?}, expected fail, ignore or replace",
+                value
+            ))),
+        }
+    }
+
+    // the statement that inserts the record
+    fn insert(self) -> &'static str {
+        match self {
+            OnConflict::Fail => "INSERT",
+            OnConflict::Ignore => "INSERT OR IGNORE",
+            OnConflict::Replace => "INSERT OR REPLACE",
+        }
+    }
+}
+
+// the outcome of a create
+enum Created {
+    Inserted(TestRecord),
+    Ignored,
+    Replaced(TestRecord),
+}
+
+impl fmt::Debug for NewRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NewRecord")
+            .field("id", &self.id)
+            .field("date", &self.date)
+            .field("message", &self.message.as_deref().map(RedactedMessage))
+            .finish()
+    }
+}
+
+// struct to hold query parameters for routes that only need a record id
+#[derive(Deserialize, Debug)]
+struct IdParams {
+    id: i32,
+}
+
+// struct to hold the pagination parameters accepted by read_data, a parameter the route doesn't
+// know, such as another route's cursor, is rejected with a 400 rather than silently ignored so
+// a client mixing paging styles finds out
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PageParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    // the id of the last record already read, the page starts after it (keyset paging)
+    after: Option<i32>,
+    // return messages that aren't UTF-8 base64 encoded instead of failing the read
+    raw: Option<String>,
+    // "array" (the default) or "map" for an object keyed by record id
+    shape: Option<String>,
+    // conditions such as "message~hello;id>5", see filter.rs
+    filter: Option<String>,
+    // "json" (the default) or "xml", see xml.rs
+    format: Option<String>,
+}
+
+// struct to hold the query parameters of the search route, other parameters are ignored as
+// older clients send the record's date and message along with its id
+#[derive(Deserialize, Debug)]
+struct SearchParams {
+    #[serde(deserialize_with = "id_format::deserialize")]
+    id: i32,
+    // "json" (the default) or "xml", see xml.rs
+    format: Option<String>,
+}
+
+// struct to hold the query string of the update and delete routes, the record's fields and the
+// dry_run control flag are parsed together so a parameter can only mean one thing, anything
+// else in the query string is rejected with a 400 instead of being silently ignored
+// a dry run executes the mutation in a transaction, reports the affected rows and rolls back
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecordParams {
+    #[serde(deserialize_with = "id_format::deserialize")]
+    id: i32,
+    date: NaiveDate,
+    message: String,
+    dry_run: Option<String>,
+}
+
+impl RecordParams {
+    // the parsed flag, a 400 when it isn't a recognised boolean
+    fn dry_run(&self) -> Result<bool, AppError> {
+        flag::parse("dry_run", self.dry_run.as_deref())
+    }
+}
+
+impl fmt::Debug for RecordParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordParams")
+            .field("id", &self.id)
+            .field("date", &self.date)
+            .field("message", &RedactedMessage(&self.message))
+            .field("dry_run", &self.dry_run)
+            .finish()
+    }
+}
+
+// struct to hold query parameters for the recent records route
+#[derive(Deserialize, Debug)]
+struct RecentParams {
+    n: Option<i64>,
+}
+
+// struct to hold the optional number of records the random route returns
+#[derive(Deserialize, Debug)]
+struct RandomParams {
+    n: Option<i64>,
+}
+
+// struct to hold query parameters for the autocomplete route
+#[derive(Deserialize, Debug)]
+struct AutocompleteParams {
+    prefix: Option<String>,
+    limit: Option<i64>,
+}
+
+// struct to hold the optional date range for the by-date route, both ends are inclusive
+// a date that isn't YYYY-MM-DD is rejected with a 400 rather than compared as text
+#[derive(Deserialize, Debug)]
+struct DateRangeParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+// struct to hold the optional cap on the number of dates the dates route returns
+#[derive(Deserialize, Debug)]
+struct DatesParams {
+    limit: Option<i64>,
+}
+
+// struct to hold the optional filter of the count route, in the ?filter= language of the read route
+#[derive(Deserialize, Debug)]
+struct CountParams {
+    filter: Option<String>,
+}
+
+// struct to hold the query parameters of the changes route, since is an RFC 3339 time and
+// after_id the id of the last record already seen at that time, other parameters are rejected
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ChangesParams {
+    since: Option<String>,
+    after_id: Option<i32>,
+    limit: Option<i64>,
+}
+
+// struct to hold the number of records stored against a date
+#[derive(Serialize, Debug, FromRow)]
+struct DateCount {
     date: String,
+    count: i64,
+}
+
+// struct to hold a column of the test table as reported by PRAGMA table_info
+#[derive(Serialize, Debug, FromRow)]
+struct ColumnInfo {
+    name: String,
+    #[sqlx(rename = "type")]
+    #[serde(rename = "type")]
+    column_type: String,
+    #[sqlx(rename = "notnull")]
+    #[serde(serialize_with = "serialize_nullable", rename = "nullable")]
+    not_null: bool,
+    #[sqlx(rename = "dflt_value")]
+    default: Option<String>,
+    // position of the column within the primary key, 0 when it isn't part of it
+    #[sqlx(rename = "pk")]
+    #[serde(serialize_with = "serialize_primary_key", rename = "primary_key")]
+    primary_key_position: i64,
+}
+
+// written as "nullable", the opposite of the pragma's notnull flag
+fn serialize_nullable<S: serde::Serializer>(
+    not_null: &bool,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(!not_null)
+}
+
+fn serialize_primary_key<S: serde::Serializer>(
+    position: &i64,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(*position > 0)
+}
+
+// struct to hold a single entry of a batch message update
+#[derive(Deserialize)]
+struct MessageUpdate {
+    id: i32,
     message: String,
 }
 
-// function to handle graceful shutdown on ctl-c
+impl fmt::Debug for MessageUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageUpdate")
+            .field("id", &self.id)
+            .field("message", &RedactedMessage(&self.message))
+            .finish()
+    }
+}
+
+// rows are read by hand so a compressed message comes back as its text
+impl FromRow<'_, SqliteRow> for TestRecord {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(TestRecord {
+            id: row.try_get("id")?,
+            date: row.try_get("date")?,
+            message: compress::load(row)?,
+            updated_at: row.try_get("updated_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+// shared application state, handlers extract the pieces they need
+#[derive(Clone, FromRef)]
+struct AppState {
+    pool: SqlitePool,
+    #[from_ref(skip)]
+    config: SharedConfig,
+    stats: Arc<RequestStats>,
+    slowest: Arc<SlowestRequests>,
+    queue: WriteQueue,
+    starting_up: StartingUp,
+    shutting_down: ShuttingDown,
+    health_pool: HealthPool,
+}
+
+// handlers and middleware extracting the config get the snapshot current when they run
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.load()
+    }
+}
+
+impl FromRef<AppState> for SharedConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+// function to handle graceful shutdown on ctl-c and SIGTERM, SIGHUP is listened for too but
+// doesn't shut the server down
 async fn shutdown_signal() {
+    // if a handler can't be installed, log it and wait forever instead of panicking
+    // the server keeps running, it just won't shut down gracefully on that signal
     let ctrl_c = async {
-        signal::ctrl_c()
-            .await
-            .expect("Failed to install Ctrl-C graceful shutdown handler");
+        if let Err(e) = signal::ctrl_c().await {
+            error!("Failed to install Ctrl-C graceful shutdown handler: {}", e);
+            pending::<()>().await;
+        }
     };
 
     // configuration for graceful shutdown on Unix platforms
     #[cfg(unix)]
     let terminate = async {
-        signal::unix::signal(signal::unix::SignalKind::terminate())
-            .expect("Failed to install signal handler")
-            .recv()
-            .await;
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to install SIGTERM graceful shutdown handler: {}", e);
+                pending::<()>().await;
+            }
+        }
     };
 
     // configuration for graceful shutdown on non-Unix platforms
     #[cfg(not(unix))]
     let terminate = pending::<()>();
 
+    // SIGHUP is what logrotate-style tools send to have a log file reopened, it would end the
+    // process by default, logs only go to stdout so there's nothing to reopen and it's just
+    // acknowledged, the server keeps running
+    #[cfg(unix)]
+    let hangup = async {
+        match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(mut sighup) => {
+                while sighup.recv().await.is_some() {
+                    info!("SIGHUP received, logs are written to stdout so there's no log file to reopen");
+                }
+            }
+            Err(e) => error!("Failed to install SIGHUP handler: {}", e),
+        }
+        pending::<()>().await;
+    };
+
+    #[cfg(not(unix))]
+    let hangup = pending::<()>();
+
     tokio::select! {
         _ = ctrl_c => {},
         _ = terminate => {},
+        _ = hangup => {},
     }
 }
 
-// handler function for the "/" root route
-async fn root() -> impl IntoResponse {
+// handler function for the "/" root route, a small dashboard with the current record count
+// and the available routes, the count shows as "unavailable" if the database can't be reached
+#[axum_macros::debug_handler]
+#[instrument(skip_all, fields(route = "/", method = "GET"))]
+async fn root(State(pool): State<SqlitePool>) -> impl IntoResponse {
+    let count = match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM test")
+        .fetch_one(&pool)
+        .await
+    {
+        Ok(count) => count.to_string(),
+        Err(e) => {
+            warn!("could not count records for the root page: {}", e);
+            "unavailable".to_string()
+        }
+    };
+
+    let routes: String = ROOT_LINKS
+        .iter()
+        .map(|(method, path, description)| {
+            if *method == "GET" {
+                format!("<li>{method} <a href=\"{path}\">{path}</a> - {description}</li>")
+            } else {
+                format!("<li>{method} {path} - {description}</li>")
+            }
+        })
+        .collect();
+
     (
         StatusCode::OK,
-        Html("<h1>Welcome to the Axum Core API</h1><h2>Available routes:</h2><p>/ - this route, the root</p><p>/health_check - current API status</p>")
+        Html(format!(
+            "<h1>Welcome to the Axum Core API</h1><h2>Records stored: {count}</h2><h2>Available routes:</h2><ul>{routes}</ul>"
+        )),
     )
 }
 
+// whether a response is an HTML page, which gets the browser-facing security headers
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+// remove the Server header from a response, SERVER_HEADER is set over it afterwards
+async fn strip_server_header(mut response: Response) -> Response {
+    response.headers_mut().remove(header::SERVER);
+    response
+}
+
 // handler function for our "/health_check" route
+#[instrument(fields(route = "/health_check", method = "GET"))]
 async fn health_check() -> impl IntoResponse {
     (
         StatusCode::OK,
@@ -78,85 +670,1238 @@ async fn health_check() -> impl IntoResponse {
     )
 }
 
-// handler function for the route which returns test data from the SQLite database
-#[axum_macros::debug_handler]
-async fn read_data(State(pool): State<SqlitePool>) -> impl IntoResponse {
-    let record = sqlx::query_as::<_, TestRecord>("SELECT * FROM test")
-        .fetch_all(&pool)
-        .await
-        .expect("There's been an error, could not retrieve the records from the database.");
+// handler function for HEAD on the root, answers uptime monitors with a 200 and the content
+// type a GET would carry, without counting records to build the page
+#[instrument(skip_all, fields(route = "/", method = "HEAD"))]
+async fn head_root() -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+    )
+}
+
+// handler function for the route which returns test data from the SQLite database, in id order
+// results are paginated when limit or offset is given, limit falls back to the configured
+// default page size and is capped at the configured maximum, an unpaginated read stops at
+// READ_HARD_LIMIT records and says so with an X-Truncated: true header, ?shape=map returns
+// an object keyed by record id instead of an array, ?filter= narrows the records read and
+// ?format=xml answers in XML, ?after= pages by id instead of offset and can't be combined with it
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_read", method = "GET"))]
+async fn read_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<PageParams>,
+) -> Result<Response, AppError> {
+    // both say where the page starts, so neither can be preferred silently
+    if params.offset.is_some() && params.after.is_some() {
+        return Err(AppError::BadRequest(
+            "offset and after can't be used together, page by one or the other".to_string(),
+        ));
+    }
+    let raw = flag::parse("raw", params.raw.as_deref())?;
+    let map = match params.shape.as_deref() {
+        None | Some("array") => false,
+        Some("map") => true,
+        Some(shape) => {
+            return Err(AppError::BadRequest(format!(
+                "invalid value for shape: {:?}, expected array or map",
+                shape
+            )))
+        }
+    };
+    let format = xml::Format::parse(params.format.as_deref())?;
+    if map && format == xml::Format::Xml {
+        return Err(AppError::BadRequest(
+            "shape=map only applies to JSON, XML records carry their id".to_string(),
+        ));
+    }
+    let filter = Filter::parse(params.filter.as_deref())?;
+    let where_clause = filter.where_clause();
+    let next = filter.placeholders() + 1;
+    let mut truncated = false;
+    let mut links = None;
+    let paginated = params.limit.is_some() || params.offset.is_some() || params.after.is_some();
+    let record = if !paginated {
+        // one row past the cap shows whether anything was left out, a negative LIMIT is no limit
+        let hard_limit = config.read_hard_limit;
+        let fetch_limit = if hard_limit > 0 { hard_limit + 1 } else { -1 };
+        let sql = format!(
+            "SELECT * FROM test{} ORDER BY id LIMIT ${}",
+            where_clause, next
+        );
+        let query = filter
+            .bind(sqlx::query(&sql))
+            .bind(fetch_limit)
+            .fetch_all(&pool);
+        let mut record = sql_timeout::limit(&config, query).await?;
+        if hard_limit > 0 && record.len() as i64 > hard_limit {
+            record.truncate(hard_limit as usize);
+            truncated = true;
+            warn!("unpaginated read truncated at {} records", hard_limit);
+        }
+        record
+    } else {
+        let limit = params.limit.unwrap_or(config.default_page_size);
+        let offset = params.offset.unwrap_or(0);
+        if limit < 1 {
+            return Err(AppError::BadRequest(
+                "limit must be a positive integer".to_string(),
+            ));
+        }
+        if offset < 0 {
+            return Err(AppError::BadRequest(
+                "offset must not be negative".to_string(),
+            ));
+        }
+
+        let limit = limit.min(config.max_page_size);
+
+        // the links repeat every parameter but the paging ones
+        let mut carried = Vec::new();
+        if raw {
+            carried.push(("raw", "true"));
+        }
+        if map {
+            carried.push(("shape", "map"));
+        }
+        if let Some(filter) = params.filter.as_deref() {
+            carried.push(("filter", filter));
+        }
+        if format == xml::Format::Xml {
+            carried.push(("format", "xml"));
+        }
+        let carried = serde_urlencoded::to_string(carried).unwrap_or_default();
+
+        if let Some(after) = params.after {
+            let keyset = if where_clause.is_empty() {
+                format!(" WHERE id > ${}", next)
+            } else {
+                format!("{} AND id > ${}", where_clause, next)
+            };
+            let sql = queries::page(&keyset, next + 1);
+            let query = filter
+                .bind(sqlx::query(&sql))
+                .bind(after)
+                .bind(limit)
+                .bind(0)
+                .fetch_all(&pool);
+            let record = sql_timeout::limit(&config, query).await?;
+            // a full page may have more after it, an id cursor has no first, prev or last
+            if record.len() as i64 == limit {
+                if let Some(last) = record.last() {
+                    links = Some(after_link(limit, last.try_get("id")?, &carried));
+                }
+            }
+            record
+        } else {
+            let sql = queries::count(&where_clause);
+            let query = filter.bind(sqlx::query(&sql)).fetch_one(&pool);
+            let total: i64 = sql_timeout::limit(&config, query).await?.try_get(0)?;
+            links = Some(page_links(limit, offset, total, &carried));
+
+            let sql = queries::page(&where_clause, next);
+            let query = filter
+                .bind(sqlx::query(&sql))
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(&pool);
+            sql_timeout::limit(&config, query).await?
+        }
+    };
+    let record = record
+        .iter()
+        .map(|row| decode::record(row, raw))
+        .collect::<Result<Vec<_>, _>>()?;
+    info!("read {} records", record.len());
+
+    let body = if format == xml::Format::Xml {
+        xml::records(&record)
+    } else if map {
+        let record = record
+            .iter()
+            .map(|record| (record.id().to_string(), json!(record)))
+            .collect::<serde_json::Map<_, _>>();
+        server_timing::json(record)
+    } else {
+        server_timing::json(record)
+    };
+    let mut response = (StatusCode::OK, body).into_response();
+    if truncated {
+        response
+            .headers_mut()
+            .insert(X_TRUNCATED.clone(), HeaderValue::from_static("true"));
+    }
+    if let Some(value) = links.and_then(|links| HeaderValue::from_str(&links).ok()) {
+        response.headers_mut().insert(header::LINK, value);
+    }
+    Ok(response)
+}
 
-    (StatusCode::OK, Json(record)).into_response()
+// RFC 5988 Link header value for a page of /database_read, with first, prev, next and last
+// pages, prev and next are left out on the first and last page, carried holds the other
+// parameters of the request, already URL encoded
+fn page_links(limit: i64, offset: i64, total: i64, carried: &str) -> String {
+    let carried = if carried.is_empty() {
+        String::new()
+    } else {
+        format!("&{}", carried)
+    };
+    let link = |offset: i64, rel: &str| {
+        format!(
+            "</database_read?limit={}&offset={}{}>; rel=\"{}\"",
+            limit, offset, carried, rel
+        )
+    };
+
+    let last = if total > 0 {
+        (total - 1) / limit * limit
+    } else {
+        0
+    };
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        links.push(link((offset - limit).max(0), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    links.push(link(last, "last"));
+    links.join(", ")
+}
+
+// Link header value for the page after a full ?after= page of /database_read, which starts after
+// the last record on it
+fn after_link(limit: i64, last_id: i32, carried: &str) -> String {
+    let carried = if carried.is_empty() {
+        String::new()
+    } else {
+        format!("&{}", carried)
+    };
+    format!(
+        "</database_read?limit={}&after={}{}>; rel=\"next\"",
+        limit, last_id, carried
+    )
 }
 
 // handler function for the route which adds some data to the SQLite database
-// data is hardcoded for the time being
-#[axum_macros::debug_handler]
+// a missing date defaults to today, a missing message defaults to the configured placeholder
+// an id that's already taken is a 409, unless ?on_conflict=ignore keeps the existing record or
+// ?on_conflict=replace overwrites it
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_create", method = "POST", id = payload.id))]
 async fn create_data(
     State(pool): State<SqlitePool>,
-    Json(payload): Json<TestRecord>,
-) -> impl IntoResponse {
-    let _result = sqlx::query("INSERT INTO test (id, date, message) VALUES ($1, $2, $3)")
-        .bind(payload.id)
-        .bind(payload.date)
-        .bind(payload.message)
-        .execute(&pool)
-        .await
-        .expect("Error writing to database, could not write new values.");
-    (
-        StatusCode::OK,
-        Html("<h1>Data added...check /database_read for results</h1>"),
-    )
+    State(config): State<Arc<Config>>,
+    State(queue): State<WriteQueue>,
+    Query(params): Query<CreateParams>,
+    RequiredJson(payload): RequiredJson<NewRecord>,
+) -> Result<Response, AppError> {
+    debug!("creating record: {:?}", payload);
+    let on_conflict = OnConflict::parse(params.on_conflict.as_deref())?;
+    let date = payload.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    let message = match payload.message {
+        Some(message) if message.trim().is_empty() => {
+            return Err(AppError::BadRequest(
+                "message must not be empty".to_string(),
+            ))
+        }
+        Some(message) => message,
+        None => config.default_message.clone(),
+    };
+
+    let record = TestRecord {
+        id: payload.id,
+        date,
+        message,
+        updated_at: None,
+        created_at: None,
+    };
+
+    // with ASYNC_WRITES the record is stored later by the write queue worker, the quota is
+    // checked before queueing so a full table is still refused up front, and again by the
+    // worker as it stores the record, as the table may have filled up in the meantime
+    // a create with on_conflict is written straight away, its answer depends on what's stored
+    if queue.is_enabled() && on_conflict == OnConflict::Fail {
+        check_quota(&mut *pool.acquire().await?, &config).await?;
+        queue.push(record.clone())?;
+        info!("record queued");
+        return Ok((
+            StatusCode::ACCEPTED,
+            server_timing::json(json!({ "queued": true, "record": record })),
+        )
+            .into_response());
+    }
+
+    // return the stored row so clients see the server-filled fields without a follow-up read
+    let (pool, config, record) = (&pool, &config, &record);
+    let created = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let created = store_record(&mut tx, config, record, on_conflict).await?;
+        tx.commit().await?;
+        Ok(created)
+    })
+    .await?;
+
+    match created {
+        Created::Inserted(record) => {
+            info!("record created");
+            let location = format!("/database/{}", record.id);
+            Ok((
+                StatusCode::CREATED,
+                [(header::LOCATION, location)],
+                server_timing::json(record),
+            )
+                .into_response())
+        }
+        Created::Ignored => {
+            info!("id already taken, create ignored");
+            Ok((
+                StatusCode::OK,
+                server_timing::json(json!({ "inserted": false })),
+            )
+                .into_response())
+        }
+        Created::Replaced(record) => {
+            info!("record replaced");
+            Ok((StatusCode::OK, server_timing::json(record)).into_response())
+        }
+    }
 }
 
-#[axum_macros::debug_handler]
+// handler function for the route which copies a record under a new, database assigned id
+// the new record is returned along with its Location
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_clone/{id}", method = "POST", id = id))]
+async fn clone_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let (pool, config) = (&pool, &config);
+    let record = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let source = find_record(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("no record with id {}", id)))?;
+        check_quota(&mut tx, config).await?;
+        let message = compress::store(config, &source.message);
+        let record = sqlx::query_as::<_, TestRecord>(
+            "INSERT INTO test (date, message, message_zstd, updated_at, created_at) \
+             VALUES ($1, $2, $3, $4, $4) RETURNING *",
+        )
+        .bind(source.date)
+        .bind(message.text)
+        .bind(message.zstd)
+        .bind(updated_at_now())
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record_change(&mut tx, record.id, audit::CREATE, None).await?;
+        outbox::enqueue(&mut tx, config, outbox::RECORD_CREATED, &record).await?;
+        tx.commit().await?;
+        Ok(record)
+    })
+    .await?;
+    info!("record cloned as {}", record.id);
+
+    let location = format!("/database/{}", record.id);
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        server_timing::json(record),
+    ))
+}
+
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_update", method = "PUT", id = params.id))]
 async fn update_data(
     State(pool): State<SqlitePool>,
-    Query(params): Query<TestRecord>,
-) -> impl IntoResponse {
-    let _result = sqlx::query("UPDATE test SET message=$3 where id=$1")
-        .bind(params.id)
-        .bind(params.message)
-        .execute(&pool)
-        .await
-        .expect("Failed to update the record.");
-    (
+    State(config): State<Arc<Config>>,
+    Query(params): Query<RecordParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let dry_run = params.dry_run()?;
+    etag::require(&config, &headers)?;
+    debug!("updating record: {:?}", params);
+    let (pool, config, params, headers) = (&pool, &config, &params, &headers);
+    let rows_affected = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let mut rows_affected = 0;
+        let before = find_record(&mut tx, params.id).await?;
+        etag::check(headers, before.as_ref())?;
+        if let Some(before) = before {
+            let message = compress::store(config, &params.message);
+            rows_affected = sqlx::query(
+                "UPDATE test SET message = $1, message_zstd = $2, updated_at = $3 WHERE id = $4",
+            )
+            .bind(message.text)
+            .bind(message.zstd)
+            .bind(updated_at_now())
+            .bind(params.id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            audit::record_change(&mut tx, params.id, audit::UPDATE, Some(&before)).await?;
+        }
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(rows_affected)
+    })
+    .await?;
+
+    if dry_run {
+        info!("dry run, update would affect {} records", rows_affected);
+        return Ok(dry_run_response(rows_affected));
+    }
+    info!("record updated");
+    Ok((
         StatusCode::OK,
         Html("<h1>Data updated...check /database_check for results</h1>"),
     )
+        .into_response())
 }
 
-#[axum_macros::debug_handler]
+// POST /database_delete predates the DELETE route and still works for existing clients,
+// each use is logged and answered with a Deprecation header so callers can find and move off it
+async fn deprecated_post_delete(request: Request, next: Next) -> Response {
+    if request.method() != Method::POST {
+        return next.run(request).await;
+    }
+    warn!("POST /database_delete is deprecated, use DELETE /database_delete instead");
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(DEPRECATION.clone(), HeaderValue::from_static("true"));
+    response
+}
+
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_delete", method = "DELETE", id = params.id))]
 async fn delete_data(
     State(pool): State<SqlitePool>,
-    Query(params): Query<TestRecord>,
-) -> impl IntoResponse {
-    let _result = sqlx::query("DELETE FROM test WHERE id = $1")
-        .bind(params.id)
-        .execute(&pool)
-        .await
-        .expect("Error deleting the record from the database.");
-    (
+    State(config): State<Arc<Config>>,
+    Query(params): Query<RecordParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let dry_run = params.dry_run()?;
+    etag::require(&config, &headers)?;
+    let (pool, headers) = (&pool, &headers);
+    let rows_affected = retry::on_busy(&config, || async move {
+        let mut tx = pool.begin().await?;
+        let mut rows_affected = 0;
+        let before = find_record(&mut tx, params.id).await?;
+        etag::check(headers, before.as_ref())?;
+        if let Some(before) = before {
+            rows_affected = sqlx::query("DELETE FROM test WHERE id = $1")
+                .bind(params.id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            audit::record_change(&mut tx, params.id, audit::DELETE, Some(&before)).await?;
+        }
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(rows_affected)
+    })
+    .await?;
+
+    if dry_run {
+        info!("dry run, delete would affect {} records", rows_affected);
+        return Ok(dry_run_response(rows_affected));
+    }
+    info!("record deleted");
+    Ok((
         StatusCode::OK,
         Html("<h1>Deleted record...check /database_check to confirm."),
     )
+        .into_response())
 }
 
-#[axum_macros::debug_handler]
+// response for a mutation run with ?dry_run=true, the changes were rolled back
+fn dry_run_response(would_affect: u64) -> Response {
+    (
+        StatusCode::OK,
+        Json(json!({ "dry_run": true, "would_affect": would_affect })),
+    )
+        .into_response()
+}
+
+// handler function for the route which returns a single record by the id in its query string,
+// in XML with ?format=xml
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_search", method = "GET", id = params.id))]
 async fn search_data(
     State(pool): State<SqlitePool>,
-    Query(params): Query<TestRecord>
-) -> impl IntoResponse {
-    let record = sqlx::query_as::<_, TestRecord>("SELECT * FROM test WHERE id = $1 ")
+    State(config): State<Arc<Config>>,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, AppError> {
+    let format = xml::Format::parse(params.format.as_deref())?;
+    let query = sqlx::query_as::<_, TestRecord>(queries::RECORD_BY_ID)
         .bind(params.id)
-        .fetch_one(&pool)
+        .fetch_optional(&pool);
+    let record = sql_timeout::limit(&config, query)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("no record with id {}", params.id)))?;
+    info!("record found");
+
+    let body = match format {
+        xml::Format::Json => server_timing::json(&record),
+        xml::Format::Xml => xml::record(&record),
+    };
+    Ok((StatusCode::OK, [etag::header(&record)], body).into_response())
+}
+
+// handler function for the route which returns a single record addressed by its path,
+// this is the URL create_data hands back in the Location header
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database/{id}", method = "GET", id = id))]
+async fn get_record(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_as::<_, TestRecord>(queries::RECORD_BY_ID)
+        .bind(id)
+        .fetch_optional(&pool);
+    let record = sql_timeout::limit(&config, query)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("no record with id {}", id)))?;
+    info!("record found");
+
+    Ok((
+        StatusCode::OK,
+        [etag::header(&record)],
+        server_timing::json(record),
+    ))
+}
+
+// handler function for the route which returns the record with the lowest id
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_first", method = "GET"))]
+async fn first_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_as::<_, TestRecord>("SELECT * FROM test ORDER BY id ASC LIMIT 1")
+        .fetch_optional(&pool);
+    let record = sql_timeout::limit(&config, query)
+        .await?
+        .ok_or_else(|| AppError::NotFound("the table has no records".to_string()))?;
+    info!("record found");
+
+    Ok((
+        StatusCode::OK,
+        [etag::header(&record)],
+        server_timing::json(record),
+    ))
+}
+
+// handler function for the route which returns the record with the highest id
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_last", method = "GET"))]
+async fn last_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_as::<_, TestRecord>("SELECT * FROM test ORDER BY id DESC LIMIT 1")
+        .fetch_optional(&pool);
+    let record = sql_timeout::limit(&config, query)
+        .await?
+        .ok_or_else(|| AppError::NotFound("the table has no records".to_string()))?;
+    info!("record found");
+
+    Ok((
+        StatusCode::OK,
+        [etag::header(&record)],
+        server_timing::json(record),
+    ))
+}
+
+// handler function for the route which returns a random record, for sampling and demos
+// without n it's a single record with its ETag, with n an array of up to n distinct records
+// ORDER BY RANDOM() numbers and sorts every row of the table to pick from it, which is fine
+// at this table's size but grows with it, a large table would want to pick random ids instead
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_random", method = "GET"))]
+async fn random_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<RandomParams>,
+) -> Result<Response, AppError> {
+    if params.n.is_some_and(|n| n < 1) {
+        return Err(AppError::BadRequest(
+            "n must be a positive integer".to_string(),
+        ));
+    }
+
+    let query = sqlx::query_as::<_, TestRecord>("SELECT * FROM test ORDER BY RANDOM() LIMIT $1")
+        .bind(params.n.unwrap_or(1).min(MAX_RANDOM))
+        .fetch_all(&pool);
+    let mut records = sql_timeout::limit(&config, query).await?;
+    if records.is_empty() {
+        return Err(AppError::NotFound("the table has no records".to_string()));
+    }
+    info!("picked {} random records", records.len());
+
+    if params.n.is_some() {
+        return Ok((StatusCode::OK, server_timing::json(records)).into_response());
+    }
+    let record = records.swap_remove(0);
+    Ok((
+        StatusCode::OK,
+        [etag::header(&record)],
+        server_timing::json(record),
+    )
+        .into_response())
+}
+
+// handler function for the route which updates the message of many records at once
+// every update runs in one transaction, ids that don't match a record are reported back,
+// any database error rolls back the whole batch
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_update_batch", method = "POST", count = updates.len()))]
+async fn update_batch(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(updates): Json<Vec<MessageUpdate>>,
+) -> Result<impl IntoResponse, AppError> {
+    if updates.len() > config.max_batch_size {
+        return Err(AppError::BadRequest(format!(
+            "batch size {} exceeds the maximum of {}",
+            updates.len(),
+            config.max_batch_size
+        )));
+    }
+    let errors = updates
+        .iter()
+        .enumerate()
+        .filter(|(_, update)| update.message.trim().is_empty())
+        .map(|(index, _)| {
+            import::FieldError::new(index, Some("message"), "message must not be empty")
+        })
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        return Err(import::reject("batch update", errors));
+    }
+
+    let (pool, config, updates) = (&pool, &config, &updates);
+    let (rows_affected, unmatched_ids) = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let mut rows_affected = 0;
+        let mut unmatched_ids = Vec::new();
+        for update in updates {
+            let Some(before) = find_record(&mut tx, update.id).await? else {
+                unmatched_ids.push(id_format::Id(update.id));
+                continue;
+            };
+            let message = compress::store(config, &update.message);
+            let result = sqlx::query(
+                "UPDATE test SET message = $1, message_zstd = $2, updated_at = $3 WHERE id = $4",
+            )
+            .bind(message.text)
+            .bind(message.zstd)
+            .bind(updated_at_now())
+            .bind(update.id)
+            .execute(&mut *tx)
+            .await?;
+            audit::record_change(&mut tx, update.id, audit::UPDATE, Some(&before)).await?;
+            rows_affected += result.rows_affected();
+        }
+        tx.commit().await?;
+        Ok((rows_affected, unmatched_ids))
+    })
+    .await?;
+    info!("batch updated {} records", rows_affected);
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "rows_affected": rows_affected, "unmatched_ids": unmatched_ids })),
+    ))
+}
+
+// handler function for the route which deletes every record in a list of ids at once
+// the rows are read and deleted with one IN (...) query each inside a transaction, so their
+// deletions are audited and can be undone like single ones, ids that don't match a record are
+// reported back, repeated ids are only counted once
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_bulk_delete", method = "POST", count = ids.len()))]
+async fn bulk_delete(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(mut ids): Json<Vec<i32>>,
+) -> Result<impl IntoResponse, AppError> {
+    if ids.len() > config.max_batch_size {
+        return Err(AppError::BadRequest(format!(
+            "batch size {} exceeds the maximum of {}",
+            ids.len(),
+            config.max_batch_size
+        )));
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok((
+            StatusCode::OK,
+            Json(json!({ "deleted": 0, "unmatched_ids": [] })),
+        ));
+    }
+
+    // $1, $2, ... one per id, only placeholders are written into the SQL
+    let placeholders = (1..=ids.len())
+        .map(|n| format!("${}", n))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let select = format!("SELECT * FROM test WHERE id IN ({})", placeholders);
+    let delete = format!("DELETE FROM test WHERE id IN ({})", placeholders);
+
+    let (pool, ids, select, delete) = (&pool, &ids, &select, &delete);
+    let (deleted, found) = retry::on_busy(&config, || async move {
+        let mut tx = pool.begin().await?;
+        let found = ids
+            .iter()
+            .fold(sqlx::query_as::<_, TestRecord>(select), |query, id| {
+                query.bind(id)
+            })
+            .fetch_all(&mut *tx)
+            .await?;
+        let deleted = ids
+            .iter()
+            .fold(sqlx::query(delete), |query, id| query.bind(id))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        for before in &found {
+            audit::record_change(&mut tx, before.id, audit::DELETE, Some(before)).await?;
+        }
+        tx.commit().await?;
+        Ok((deleted, found))
+    })
+    .await?;
+    let unmatched_ids = ids
+        .iter()
+        .filter(|id| !found.iter().any(|record| record.id == **id))
+        .map(|id| id_format::Id(*id))
+        .collect::<Vec<_>>();
+    info!("bulk deleted {} records", deleted);
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "deleted": deleted, "unmatched_ids": unmatched_ids })),
+    ))
+}
+
+// handler function for the route which reverses the most recent change to a record
+// a deleted row is re-inserted from its before-image, an update is rolled back to the previous
+// values and a create is removed, the record as it stands after the undo is returned
+// (for an undone create that's the row that was removed)
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/admin/undo", method = "POST", id = params.id))]
+async fn undo_change(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Json(params): Json<IdParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let (pool, config) = (&pool, &config);
+    let (operation, record) = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let not_found = || AppError::NotFound(format!("nothing to undo for record {}", params.id));
+        let entry = audit::latest_change(&mut tx, params.id)
+            .await?
+            .ok_or_else(not_found)?;
+        let before = entry.before_image.map(|image| image.0);
+
+        let record = match (entry.operation.as_str(), before) {
+            (audit::CREATE, _) => {
+                sqlx::query_as::<_, TestRecord>("DELETE FROM test WHERE id = $1 RETURNING *")
+                    .bind(params.id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            }
+            (audit::UPDATE, Some(before)) => {
+                let message = compress::store(config, &before.message);
+                sqlx::query_as::<_, TestRecord>(
+                    "UPDATE test SET date = $1, message = $2, message_zstd = $3, updated_at = $4 \
+                     WHERE id = $5 RETURNING *",
+                )
+                .bind(before.date)
+                .bind(message.text)
+                .bind(message.zstd)
+                .bind(updated_at_now())
+                .bind(params.id)
+                .fetch_optional(&mut *tx)
+                .await?
+            }
+            (audit::DELETE, Some(before)) => {
+                let message = compress::store(config, &before.message);
+                sqlx::query_as::<_, TestRecord>(
+                    "INSERT INTO test (id, date, message, message_zstd, updated_at, created_at) \
+                     VALUES ($1, $2, $3, $4, $5, COALESCE($6, $5)) RETURNING *",
+                )
+                .bind(before.id)
+                .bind(before.date)
+                .bind(message.text)
+                .bind(message.zstd)
+                .bind(updated_at_now())
+                .bind(before.created_at)
+                .fetch_optional(&mut *tx)
+                .await?
+            }
+            _ => None,
+        };
+        let record = record.ok_or_else(not_found)?;
+
+        audit::mark_undone(&mut tx, entry.id).await?;
+        tx.commit().await?;
+        Ok((entry.operation, record))
+    })
+    .await?;
+    info!("undid {} of record", operation);
+
+    Ok((StatusCode::OK, Json(record)))
+}
+
+// handler function for the route which re-reads the configuration from the environment and
+// swaps it in for subsequent requests, settings only applied at startup keep their values and
+// are listed under restart_required when they've changed, the effective config is returned
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/admin/reload", method = "POST"))]
+async fn reload_config(State(shared): State<SharedConfig>) -> Result<impl IntoResponse, AppError> {
+    let (config, restart_required) = shared
+        .load()
+        .reload()
+        .map_err(|e| AppError::InvalidConfig(e.to_string()))?;
+
+    id_format::set_id_as_string(config.id_as_string);
+    redact::set_redact_messages(config.log_redact_messages);
+    warn_if_chaos(&config);
+    if !restart_required.is_empty() {
+        warn!(
+            "configuration reloaded, changes to {:?} need a restart",
+            restart_required
+        );
+    } else {
+        info!("configuration reloaded");
+    }
+
+    let effective = config.redacted();
+    shared.store(Arc::new(config));
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "config": effective, "restart_required": restart_required })),
+    ))
+}
+
+// warn at startup when the database can be read but not written, a read-only file or mount
+// otherwise only shows up as 503s on the first write
+async fn check_writable(pool: &SqlitePool, config: &Config) {
+    // a table created in a transaction that's rolled back, so nothing is left behind
+    let probe = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("CREATE TABLE write_probe (id INTEGER)")
+            .execute(&mut *tx)
+            .await?;
+        tx.rollback().await
+    };
+    match probe.await {
+        Ok(()) => debug!("database is writable"),
+        Err(e) if error::is_read_only(&e) => warn!(
+            "the database at {} is read-only, reads will work but every write will fail with a 503",
+            config.database_file()
+        ),
+        Err(e) => warn!("could not check whether the database is writable: {}", e),
+    }
+}
+
+// announce chaos mode at startup and on reload, it should never be mistaken for real faults
+fn warn_if_chaos(config: &Config) {
+    if chaos::is_active(config) {
+        warn!(
+            "CHAOS MODE IS ACTIVE: every request is delayed by {} ms and {:.0}% of requests fail with a 500",
+            config.chaos_delay_ms,
+            config.chaos_error_rate * 100.0
+        );
+    }
+}
+
+// handler function for the route which reports the configuration in effect, with secrets
+// redacted as /admin/reload does, alongside what the server derived from it at startup
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/admin/config", method = "GET"))]
+async fn show_config(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> impl IntoResponse {
+    let listen = match &config.unix_socket_path {
+        Some(path) => format!("unix:{}", path),
+        None => SocketAddr::from(([127, 0, 0, 1], config.port)).to_string(),
+    };
+    let database = redact::connection_string(&config.database_url);
+
+    (
+        StatusCode::OK,
+        Json(json!({
+            "config": config.redacted(),
+            "runtime": {
+                "listen": listen,
+                "database": database,
+                "pool_max_connections": POOL_MAX_CONNECTIONS,
+                "pool_size": pool.size(),
+                "pool_idle": pool.num_idle(),
+            },
+        })),
+    )
+}
+
+// insert a record with its audit entry and outbox event, returning the stored row
+async fn insert_record(
+    conn: &mut SqliteConnection,
+    config: &Config,
+    record: &TestRecord,
+) -> Result<TestRecord, sqlx::Error> {
+    let message = compress::store(config, &record.message);
+    let stored = sqlx::query_as::<_, TestRecord>(queries::INSERT_RECORD)
+        .bind(record.id)
+        .bind(record.date)
+        .bind(message.text)
+        .bind(message.zstd)
+        .bind(updated_at_now())
+        .fetch_one(&mut *conn)
+        .await?;
+    audit::record_change(conn, stored.id, audit::CREATE, None).await?;
+    outbox::enqueue(conn, config, outbox::RECORD_CREATED, &stored).await?;
+    Ok(stored)
+}
+
+// the time a write is stamped with, in the same format the migration's triggers use so the
+// two sort together, statements set updated_at themselves so RETURNING sees the new value
+fn updated_at_now() -> String {
+    Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+// store a new record with the given conflict handling, checking the quota when it adds a row
+// a replaced record is audited as an update of the one it overwrote so it can be undone,
+// the outbox only hears about records that are new
+async fn store_record(
+    conn: &mut SqliteConnection,
+    config: &Config,
+    record: &TestRecord,
+    on_conflict: OnConflict,
+) -> Result<Created, AppError> {
+    if on_conflict == OnConflict::Fail {
+        check_quota(conn, config).await?;
+        return Ok(Created::Inserted(
+            insert_record(conn, config, record).await?,
+        ));
+    }
+
+    let before = find_record(conn, record.id).await?;
+    if before.is_none() {
+        check_quota(conn, config).await?;
+    }
+    let message = compress::store(config, &record.message);
+    let sql = format!(
+        "{} INTO test (id, date, message, message_zstd, updated_at, created_at) \
+         VALUES ($1, $2, $3, $4, $5, COALESCE($6, $5)) RETURNING *",
+        on_conflict.insert()
+    );
+    // INSERT OR IGNORE returns no row when it skipped the record
+    let stored = sqlx::query_as::<_, TestRecord>(&sql)
+        .bind(record.id)
+        .bind(record.date)
+        .bind(message.text)
+        .bind(message.zstd)
+        .bind(updated_at_now())
+        .bind(before.as_ref().and_then(|before| before.created_at.clone()))
+        .fetch_optional(&mut *conn)
+        .await?;
+
+    match (stored, before) {
+        (None, _) => Ok(Created::Ignored),
+        (Some(stored), Some(before)) => {
+            audit::record_change(conn, stored.id, audit::UPDATE, Some(&before)).await?;
+            Ok(Created::Replaced(stored))
+        }
+        (Some(stored), None) => {
+            audit::record_change(conn, stored.id, audit::CREATE, None).await?;
+            outbox::enqueue(conn, config, outbox::RECORD_CREATED, &stored).await?;
+            Ok(Created::Inserted(stored))
+        }
+    }
+}
+
+// refuse to add a record once the test table holds MAX_RECORDS, run inside the inserting
+// transaction so concurrent creates can't both pass the count and overshoot the quota
+async fn check_quota(conn: &mut SqliteConnection, config: &Config) -> Result<(), AppError> {
+    if config.max_records == 0 {
+        return Ok(());
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM test")
+        .fetch_one(conn)
+        .await?;
+    if count >= config.max_records {
+        warn!("record quota of {} reached", config.max_records);
+        return Err(AppError::Forbidden("record quota reached".to_string()));
+    }
+    Ok(())
+}
+
+// fetch a single record by id, used to capture before-images for the audit log
+async fn find_record(
+    conn: &mut SqliteConnection,
+    id: i32,
+) -> Result<Option<TestRecord>, sqlx::Error> {
+    sqlx::query_as::<_, TestRecord>(queries::RECORD_BY_ID)
+        .bind(id)
+        .fetch_optional(conn)
         .await
-        .expect("There's been an error, could not retrieve the record from the database.");
+}
+
+// handler function for the route which returns the most recently created records, newest first
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_recent", method = "GET"))]
+async fn recent_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<RecentParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let n = params.n.unwrap_or(DEFAULT_RECENT);
+    if n < 1 {
+        return Err(AppError::BadRequest(
+            "n must be a positive integer".to_string(),
+        ));
+    }
+
+    let query = sqlx::query_as::<_, TestRecord>("SELECT * FROM test ORDER BY id DESC LIMIT $1")
+        .bind(n.min(MAX_RECENT))
+        .fetch_all(&pool);
+    let records = sql_timeout::limit(&config, query).await?;
+    info!("read {} recent records", records.len());
+
+    Ok((StatusCode::OK, server_timing::json(records)))
+}
+
+// handler function for the route which suggests messages starting with a prefix, for
+// search-as-you-type, case-insensitive and in alphabetical order, without duplicates
+// a prefix with no LIKE wildcards in it is matched so the NOCASE index on message is used
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_autocomplete", method = "GET"))]
+async fn autocomplete_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<AutocompleteParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let prefix = params.prefix.unwrap_or_default();
+    if prefix.is_empty() {
+        return Err(AppError::BadRequest("prefix must not be empty".to_string()));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_AUTOCOMPLETE);
+    if limit < 1 {
+        return Err(AppError::BadRequest(
+            "limit must be a positive integer".to_string(),
+        ));
+    }
 
-    (StatusCode::OK, Json(record)).into_response()
+    // SQLite only uses the index for a LIKE without an ESCAPE clause
+    let (sql, pattern) = if prefix.contains(['%', '_', '\\']) {
+        (
+            queries::AUTOCOMPLETE_ESCAPED,
+            format!("{}%", filter::escape_like(&prefix)),
+        )
+    } else {
+        (queries::AUTOCOMPLETE, format!("{}%", prefix))
+    };
+    let query = sqlx::query_scalar::<_, String>(sql)
+        .bind(pattern)
+        .bind(limit.min(MAX_AUTOCOMPLETE))
+        .fetch_all(&pool);
+    let messages = sql_timeout::limit(&config, query).await?;
+    info!("suggested {} messages", messages.len());
+
+    Ok((StatusCode::OK, server_timing::json(messages)))
+}
+
+// handler function for the route which counts records per date, optionally within a date range
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_by_date", method = "GET"))]
+async fn by_date_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<DateRangeParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_as::<_, DateCount>(
+        "SELECT date, COUNT(*) AS count FROM test \
+         WHERE ($1 IS NULL OR date >= $1) AND ($2 IS NULL OR date <= $2) \
+         GROUP BY date ORDER BY date",
+    )
+    .bind(params.from)
+    .bind(params.to)
+    .fetch_all(&pool);
+    let counts = sql_timeout::limit(&config, query).await?;
+    info!("counted records for {} dates", counts.len());
+
+    Ok((StatusCode::OK, server_timing::json(counts)))
+}
+
+// handler function for the route which counts the records matching a filter, every record
+// without one, so a client can size a filtered read before fetching it
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_count", method = "GET"))]
+async fn count_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<CountParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let filter = Filter::parse(params.filter.as_deref())?;
+    let sql = queries::count(&filter.where_clause());
+    let query = filter.bind(sqlx::query(&sql)).fetch_one(&pool);
+    let count: i64 = sql_timeout::limit(&config, query).await?.try_get(0)?;
+    info!("counted {} records", count);
+
+    Ok((
+        StatusCode::OK,
+        server_timing::json(json!({ "count": count })),
+    ))
+}
+
+// handler function for the route which lists the distinct dates in the table, for date pickers
+// limit caps the number of dates, without it every date is returned
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_dates", method = "GET"))]
+async fn dates_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<DatesParams>,
+) -> Result<impl IntoResponse, AppError> {
+    if params.limit.is_some_and(|limit| limit < 1) {
+        return Err(AppError::BadRequest(
+            "limit must be a positive integer".to_string(),
+        ));
+    }
+
+    // a negative LIMIT is no limit
+    let query =
+        sqlx::query_scalar::<_, String>("SELECT DISTINCT date FROM test ORDER BY date LIMIT $1")
+            .bind(params.limit.unwrap_or(-1))
+            .fetch_all(&pool);
+    let dates = sql_timeout::limit(&config, query).await?;
+    info!("listed {} dates", dates.len());
+
+    Ok((StatusCode::OK, server_timing::json(dates)))
+}
+
+// handler function for the route which returns the records written after a point in time, for
+// clients syncing incrementally, oldest change first and a page at a time
+// records written in the same millisecond share an updated_at, so a page ending among them is
+// continued with after_id, the next page starts after (since, after_id) of the last record
+// deletions aren't reported, a deleted record simply stops appearing
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_changes", method = "GET"))]
+async fn changes_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<ChangesParams>,
+) -> Result<impl IntoResponse, AppError> {
+    // after_id only means something next to the since it was read at
+    if params.after_id.is_some() && params.since.is_none() {
+        return Err(AppError::BadRequest(
+            "after_id can only be used together with since".to_string(),
+        ));
+    }
+    // normalised to the stored format so the two compare as text
+    let since = match params.since.as_deref() {
+        Some(since) => DateTime::parse_from_rfc3339(since)
+            .map_err(|_| {
+                AppError::BadRequest(format!(
+                    "since must be an RFC 3339 time such as 2026-10-16T12:00:00Z, got {:?}",
+                    since
+                ))
+            })?
+            .with_timezone(&Utc)
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string(),
+        None => String::new(),
+    };
+    let limit = params.limit.unwrap_or(config.default_page_size);
+    if limit < 1 {
+        return Err(AppError::BadRequest(
+            "limit must be a positive integer".to_string(),
+        ));
+    }
+
+    // reservations aren't records yet, they're reported once confirmed
+    let query = sqlx::query_as::<_, TestRecord>(queries::CHANGES)
+        .bind(since)
+        .bind(params.after_id.unwrap_or(i32::MAX))
+        .bind(limit.min(config.max_page_size))
+        .fetch_all(&pool);
+    let records = sql_timeout::limit(&config, query).await?;
+    info!("read {} changed records", records.len());
+
+    Ok((StatusCode::OK, server_timing::json(records)))
+}
+
+// handler function for the route which checks whether a record id is already taken
+// a non-integer id is rejected with a 400 by the Query extractor
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_exists", method = "GET", id = params.id))]
+async fn exists_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<IdParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM test WHERE id = $1)")
+        .bind(params.id)
+        .fetch_one(&pool);
+    let exists = sql_timeout::limit(&config, query).await?;
+    info!("record exists: {}", exists);
+
+    Ok((
+        StatusCode::OK,
+        server_timing::json(json!({ "exists": exists })),
+    ))
+}
+
+// handler function for the route which describes the columns of the test table, so clients
+// can build forms without hardcoding the fields
+#[axum_macros::debug_handler(state = AppState)]
+#[instrument(skip_all, fields(route = "/database_schema", method = "GET"))]
+async fn schema_data(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_as::<_, ColumnInfo>("PRAGMA table_info(test)").fetch_all(&pool);
+    let columns = sql_timeout::limit(&config, query).await?;
+    // the pragma returns no rows rather than an error for a missing table
+    if columns.is_empty() {
+        return Err(AppError::NotFound("table test does not exist".to_string()));
+    }
+    info!("described {} columns", columns.len());
+
+    Ok((
+        StatusCode::OK,
+        server_timing::json(json!({ "table": "test", "columns": columns })),
+    ))
+}
+
+// builds the OPTIONS handler for a route, answering 204 with an Allow header
+// listing the methods the route supports
+fn allow<S>(methods: &'static str) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    options(move || async move { (StatusCode::NO_CONTENT, [(header::ALLOW, methods)]) })
 }
 
 // handler function for non existent routes, returns a 404 Not Found
+#[instrument(skip_all, fields(route = "fallback"))]
 async fn not_found_404() -> impl IntoResponse {
     (
         StatusCode::NOT_FOUND,
@@ -167,46 +1912,817 @@ async fn not_found_404() -> impl IntoResponse {
 // main application
 #[tokio::main]
 async fn main() -> Result<()> {
-    // initialize color_eyre for nice looking error messages
-    color_eyre::install()?;
+    // initialize color_eyre for nice looking error messages, the panic hook is installed
+    // once the config is known since it can be routed through tracing instead
+    let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default().into_hooks();
+    eyre_hook.install()?;
+
+    // read configuration from the environment, first so it can pick the log format
+    let config = Arc::new(Config::from_env()?);
 
-    // initialize tracing
+    // initialize tracing, log lines are written out by a background thread so requests never
+    // wait on stdout, the guard lives until main returns and writes out what's still queued
+    let (log_writer, _log_guard) = log_writer::non_blocking(std::io::stdout())
+        .map_err(|e| eyre!("could not start the log writer thread: {}", e))?;
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::TRACE)
-        .finish();
-    set_global_default(subscriber)?;
+        .with_writer(log_writer);
+    match config.log_format {
+        LogFormat::Pretty => set_global_default(subscriber.finish())?,
+        LogFormat::Json => set_global_default(
+            subscriber
+                .fmt_fields(json_log::JsonFields)
+                .event_format(json_log::JsonFormat)
+                .finish(),
+        )?,
+    }
+
+    // panics anywhere in the process, including background tasks, are logged through
+    // tracing with color_eyre's report and backtrace rather than written straight to stderr
+    if config.log_panics {
+        std::panic::set_hook(Box::new(move |panic_info| {
+            error!(target: "panic", "{}", panic_hook.panic_report(panic_info));
+        }));
+    } else {
+        panic_hook.install();
+    }
+
+    // ids are written as numbers unless ID_AS_STRING is set
+    id_format::set_id_as_string(config.id_as_string);
+
+    // keep message content out of the logs unless explicitly allowed
+    redact::set_redact_messages(config.log_redact_messages);
+    if !config.log_redact_messages {
+        warn!("LOG_REDACT_MESSAGES is off, message content may appear in the logs");
+    }
+
+    if !config.allowed_ips.is_empty() {
+        info!("client IP allowlist: {:?}", config.allowed_ips);
+    }
+
+    if config.api_key.is_none() {
+        warn!("API_KEY is not set, routes guarded by the API key will reject every request");
+    }
 
     // SQLite database pool setup
-    let db_connection_str = "sqlite://db/test.db";
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(db_connection_str)
-        .await?;
+    // credentials in the connection string are masked wherever it's logged or reported
+    let masked_connection_str = redact::connection_string(&config.database_url);
+    info!("connecting to the database at {}", masked_connection_str);
+    // SQLx's statement log can't show parameters, development installs a trace that can
+    let statement_cache_capacity = config.statement_cache_capacity;
+    let mut connect_options = SqliteConnectOptions::from_str(&config.database_url)
+        .map_err(|e| eyre!("invalid DATABASE_URL {}: {}", masked_connection_str, e))?
+        .statement_cache_capacity(statement_cache_capacity);
+    connect_options.disable_statement_logging();
+    let health_connect_options = connect_options.clone();
+    let mut pool_options = SqlitePoolOptions::new()
+        .max_connections(POOL_MAX_CONNECTIONS)
+        .before_acquire(move |conn, meta| {
+            statement_cache::log_reuse(conn, meta, statement_cache_capacity)
+        });
+    let trace_statements = config.app_env == AppEnv::Development;
+    if trace_statements {
+        warn!("APP_ENV=development, SQL statements are logged at debug level with their bound parameters");
+    } else {
+        info!("APP_ENV=production, SQL statement logging is disabled");
+    }
+    // every new connection gets the statement trace in development and the SQLITE_PRAGMAS
+    let sqlite_pragmas = Arc::new(config.sqlite_pragmas.clone());
+    if trace_statements || !sqlite_pragmas.is_empty() {
+        let sqlite_pragmas = sqlite_pragmas.clone();
+        pool_options = pool_options.after_connect(move |conn, _| {
+            let sqlite_pragmas = sqlite_pragmas.clone();
+            Box::pin(async move {
+                if trace_statements {
+                    sql_log::trace_statements(conn).await?;
+                }
+                pragmas::apply(conn, &sqlite_pragmas).await
+            })
+        });
+    }
+    warn_if_chaos(&config);
+    let pool = pool_options
+        .connect_with(connect_options)
+        .await
+        .map_err(|e| {
+            eyre!(
+                "could not connect to the database at {}: {}",
+                masked_connection_str,
+                e
+            )
+        })?;
+
+    pragmas::log_effective(&pool, &sqlite_pragmas).await;
+
+    // one connection set aside for /readyz, opened now and kept open
+    let health_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .min_connections(1)
+        .connect_with(health_connect_options)
+        .await
+        .map_err(|e| {
+            eyre!(
+                "could not connect to the database at {}: {}",
+                masked_connection_str,
+                e
+            )
+        })?;
+
+    // queue for deferred inserts, only created with ASYNC_WRITES
+    let (queue, queued_writes) = if config.async_writes {
+        let (queue, receiver) = WriteQueue::new(config.async_write_queue_size);
+        (queue, Some(receiver))
+    } else {
+        (WriteQueue::disabled(), None)
+    };
+
+    // shared state holding the database connection pool and config
+    let state = AppState {
+        pool,
+        config: SharedConfig::new(config.clone()),
+        stats: Arc::new(RequestStats::default()),
+        slowest: Arc::new(SlowestRequests::default()),
+        queue,
+        starting_up: StartingUp::new(),
+        shutting_down: ShuttingDown::new(),
+        health_pool: HealthPool(health_pool),
+    };
+
+    // cancelled SHUTDOWN_GRACE_SECS after a shutdown signal arrives, the server and background
+    // tasks watch it, in between new requests are refused while in-flight ones finish, a second
+    // signal ends the wait
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        let shutting_down = state.shutting_down.clone();
+        let grace = Duration::from_secs(config.shutdown_grace_secs);
+        async move {
+            shutdown_signal().await;
+            shutting_down.begin();
+            if !grace.is_zero() {
+                info!(
+                    "shutting down in {} seconds, new requests are refused",
+                    grace.as_secs()
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(grace) => {}
+                    _ = shutdown_signal() => info!("second shutdown signal, stopping now"),
+                    _ = shutdown.cancelled() => {}
+                }
+            }
+            shutdown.cancel();
+        }
+    });
+
+    // cancelled once the server has stopped and answered its in-flight requests, the write
+    // queue keeps accepting records until then
+    let server_stopped = CancellationToken::new();
+
+    // the schema is brought up to date while the server already listens, the database routes
+    // answer 503 until it's done and the background tasks, which use the database, start after
+    // it, a failed migration stops the server
+    let migrating = tokio::spawn({
+        let state = state.clone();
+        let config = config.clone();
+        let shutdown = shutdown.clone();
+        let server_stopped = server_stopped.clone();
+        async move {
+            if let Err(e) = migrations::run(&state.pool).await {
+                shutdown.cancel();
+                return Err(e);
+            }
+            check_writable(&state.pool, &config).await;
+            state.starting_up.finish();
+            info!("startup finished, database routes are available");
+            Ok(spawn_background_tasks(
+                &state,
+                &config,
+                queued_writes,
+                &shutdown,
+                &server_stopped,
+            ))
+        }
+    });
+
+    // read-only database routes, successful responses carry the configured Cache-Control
+    let read_cache_control = config.read_cache_control.clone();
+    let content_security_policy = config.content_security_policy.clone();
+    let server_header = config.server_header.clone();
+    let reads = Router::new()
+        .route(
+            "/database_read",
+            get(read_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_view",
+            get(view::view_records).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_search",
+            get(search_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database/{id}",
+            get(get_record).merge(allow("GET, HEAD, PATCH, OPTIONS")),
+        )
+        .route(
+            "/database_first",
+            get(first_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_last",
+            get(last_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_random",
+            get(random_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_exists",
+            get(exists_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_recent",
+            get(recent_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_count",
+            get(count_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_by_date",
+            get(by_date_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_autocomplete",
+            get(autocomplete_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_changes",
+            get(changes_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_dates",
+            get(dates_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/database_schema",
+            get(schema_data).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/db/{table}/read",
+            get(tables::read_rows).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/db/{table}/{id}",
+            get(tables::get_row).merge(allow("GET, HEAD, PUT, DELETE, OPTIONS")),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            config.request_timeout_secs,
+            timeout::limit,
+        ))
+        // exports run under the longer bulk timeout
+        .merge(
+            Router::new()
+                .route(
+                    "/database_stream",
+                    get(stream::stream_data).merge(allow("GET, HEAD, OPTIONS")),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    config.bulk_request_timeout_secs,
+                    timeout::limit,
+                )),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            startup::reject_while_starting,
+        ))
+        .route_layer(SetResponseHeaderLayer::if_not_present(
+            header::CACHE_CONTROL,
+            move |response: &Response| {
+                response
+                    .status()
+                    .is_success()
+                    .then(|| read_cache_control.clone())
+            },
+        ));
+
+    // database routes which modify data, responses are never cached
+    let writes = Router::new()
+        .route(
+            "/database_create",
+            post(create_data).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/database_clone/{id}",
+            post(clone_data).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/database_update",
+            put(update_data).merge(allow("PUT, OPTIONS")),
+        )
+        .route(
+            "/database_reserve",
+            post(reservations::reserve).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/database_confirm/{id}",
+            put(reservations::confirm).merge(allow("PUT, OPTIONS")),
+        )
+        .route(
+            "/database_increment/{id}",
+            post(increment::increment).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/database_delete",
+            delete(delete_data)
+                .post(delete_data)
+                .route_layer(middleware::from_fn(deprecated_post_delete))
+                .merge(allow("DELETE, POST, OPTIONS")),
+        )
+        // OPTIONS for these paths is answered alongside their GET in the read routes
+        .route("/database/{id}", patch(patch::patch_record))
+        .route(
+            "/db/{table}/create",
+            post(tables::create_row).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/db/{table}/{id}",
+            put(tables::update_row).delete(tables::delete_row),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            config.request_timeout_secs,
+            timeout::limit,
+        ));
+
+    // routes guarded by the API key
+    let protected = Router::new()
+        .route(
+            "/admin/undo",
+            post(undo_change).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/admin/reload",
+            post(reload_config).merge(allow("POST, OPTIONS")),
+        )
+        .route(
+            "/admin/config",
+            get(show_config).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/admin/migrations",
+            get(migrations::migration_status).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/admin/slowest",
+            get(slowest::list_slowest).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            config.request_timeout_secs,
+            timeout::limit,
+        ))
+        // batch jobs run under the longer bulk timeout
+        .merge(
+            Router::new()
+                .route(
+                    "/database_update_batch",
+                    post(update_batch).merge(allow("POST, OPTIONS")),
+                )
+                .route(
+                    "/database_bulk_delete",
+                    post(bulk_delete).merge(allow("POST, OPTIONS")),
+                )
+                .route(
+                    "/database_import",
+                    post(import::import_data).merge(allow("POST, OPTIONS")),
+                )
+                .route(
+                    "/database_import_ndjson",
+                    post(import_ndjson::import_ndjson).merge(allow("POST, OPTIONS")),
+                )
+                .route(
+                    "/admin/vacuum",
+                    post(vacuum::vacuum_database).merge(allow("POST, OPTIONS")),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    config.bulk_request_timeout_secs,
+                    timeout::limit,
+                )),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_api_key,
+        ));
+    let writes = writes
+        .merge(protected)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            startup::reject_while_starting,
+        ))
+        .route_layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ));
 
-    // routes for our core API application, store the database connection pool in state
+    // routes for our core API application
     let app = Router::new()
         // root route
-        .route("/", get(root))
+        .route(
+            "/",
+            get(root).head(head_root).merge(allow("GET, HEAD, OPTIONS")),
+        )
         // health_check route
-        .route("/health_check", get(health_check))
-        .route("/database_read", get(read_data))
-        .route("/database_create", post(create_data))
-        .route("/database_update", put(update_data))
-        .route("/database_delete", post(delete_data))
-        .route("/database_search", get(search_data))
-        .with_state(pool);
-
-    let app = app.fallback(not_found_404);
-
-    // spin up and listen on port 127.0.0.1:3000
-    let port = 3000;
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    info!("listening on port: {}", addr);
-
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+        .route(
+            "/health_check",
+            get(health_check)
+                .head(health_check)
+                .merge(allow("GET, HEAD, OPTIONS")),
+        )
+        // readiness route, checks the database and free disk space
+        .route(
+            "/readyz",
+            get(readiness::readyz).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        // admin page, the key is asked for by the page and checked by the routes it calls
+        .route(
+            "/admin",
+            get(admin_page::admin_page).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/admin/page.js",
+            get(admin_page::admin_script).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .route(
+            "/admin/page.css",
+            get(admin_page::admin_style).merge(allow("GET, HEAD, OPTIONS")),
+        )
+        .merge(reads)
+        .merge(writes)
+        .fallback(not_found_404)
+        // development only fault injection, inside every other layer so it's measured and logged
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            chaos::inject_faults,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            allowlist::check_client_ip,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            server_timing::add_server_timing,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            stats::count_requests,
+        ))
+        // requests over the concurrency limit wait for a slot before any other work is done
+        .layer(middleware::from_fn_with_state(
+            concurrency::ConcurrencyLimit::new(
+                config.max_concurrent_requests,
+                Duration::from_secs(config.request_timeout_secs),
+            ),
+            concurrency::limit_requests,
+        ))
+        // an oversized URI is rejected before it takes a concurrency slot
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            uri_limit::check_uri_len,
+        ))
+        // wraps every JSON response, including the errors of the layers above
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            envelope::wrap_responses,
+        ))
+        // declares UTF-8 on the Content-Type the envelope settled on
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            charset::add_charset,
+        ))
+        // security headers, every response is kept from being sniffed as another type and leaks
+        // no Referer, HTML pages also get the Content-Security-Policy and can't be framed
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_FRAME_OPTIONS,
+            |response: &Response| is_html(response).then(|| HeaderValue::from_static("DENY")),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CONTENT_SECURITY_POLICY,
+            move |response: &Response| {
+                (is_html(response) && !content_security_policy.is_empty())
+                    .then(|| content_security_policy.clone())
+            },
+        ))
+        // nothing about the server's software is advertised unless SERVER_HEADER asks for it,
+        // any Server header set inside is dropped and the configured one put in its place
+        .layer(map_response(strip_server_header))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::SERVER,
+            move |_: &Response| server_header.clone(),
+        ))
+        // logs the final status and size, after the envelope has been applied
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            access_log::log_request,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            error::with_request_context,
+        ))
+        .with_state(state.clone());
+
+    // trailing slashes are dealt with before routing, so around the finished router, and once
+    // shutdown has begun every request is refused before anything else sees it
+    let app = Router::new()
+        .fallback_service(app)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            trailing_slash::normalize,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state,
+            draining::reject_while_shutting_down,
+        ));
+
+    serve(app, &config, shutdown).await?;
+    info!("server stopped, waiting for background tasks");
+    server_stopped.cancel();
+
+    // a migration still running when the server stopped is finished first
+    for task in migrating.await?? {
+        task.await?;
+    }
 
     Ok(())
 }
+
+// start the tasks that run alongside the server, they're awaited after it stops so they
+// finish cleanly
+fn spawn_background_tasks(
+    state: &AppState,
+    config: &Arc<Config>,
+    queued_writes: Option<Receiver<TestRecord>>,
+    shutdown: &CancellationToken,
+    server_stopped: &CancellationToken,
+) -> Vec<JoinHandle<()>> {
+    let mut background = Vec::new();
+    if config.stats_interval_secs > 0 {
+        background.push(tokio::spawn(stats::log_stats(
+            state.pool.clone(),
+            state.stats.clone(),
+            Duration::from_secs(config.stats_interval_secs),
+            shutdown.clone(),
+        )));
+    }
+    if config.vacuum_interval_secs > 0 {
+        background.push(tokio::spawn(vacuum::vacuum_periodically(
+            state.pool.clone(),
+            config.database_file().to_string(),
+            Duration::from_secs(config.vacuum_interval_secs),
+            shutdown.clone(),
+        )));
+    }
+    if let Some(receiver) = queued_writes {
+        info!(
+            "asynchronous writes enabled, up to {} creates are queued",
+            config.async_write_queue_size
+        );
+        background.push(tokio::spawn(write_queue::drain_writes(
+            state.pool.clone(),
+            config.clone(),
+            receiver,
+            server_stopped.clone(),
+        )));
+    }
+    if config.reservation_sweep_interval_secs > 0 {
+        background.push(tokio::spawn(reservations::sweep_periodically(
+            state.pool.clone(),
+            Duration::from_secs(config.reservation_sweep_interval_secs),
+            shutdown.clone(),
+        )));
+    }
+    if let Some(url) = config.webhook_url.clone() {
+        info!("webhook delivery enabled, events are queued in the outbox");
+        background.push(tokio::spawn(outbox::deliver_events(
+            state.pool.clone(),
+            config.clone(),
+            url,
+            shutdown.clone(),
+        )));
+    }
+    background
+}
+
+// explain a failed TCP bind, the common causes get a hint at the fix
+fn bind_error(addr: SocketAddr, e: std::io::Error) -> color_eyre::Report {
+    match e.kind() {
+        ErrorKind::AddrInUse => eyre!(
+            "port {} already in use; set PORT to another value",
+            addr.port()
+        ),
+        ErrorKind::PermissionDenied => eyre!(
+            "permission denied binding {}; ports below 1024 usually need elevated privileges, set PORT to another value",
+            addr
+        ),
+        ErrorKind::AddrNotAvailable => eyre!("address {} is not available on this machine", addr),
+        _ => eyre!("could not bind {}: {}", addr, e),
+    }
+}
+
+// serve the application until the shutdown token is cancelled, over a Unix domain socket
+// when UNIX_SOCKET_PATH is set and over TCP otherwise
+async fn serve(app: Router, config: &Config, shutdown: CancellationToken) -> Result<()> {
+    let settings = http_server::Settings::from_config(config);
+    info!(
+        "HTTP connections: header read timeout={:?}, keep-alive={}",
+        settings.header_read, settings.keep_alive
+    );
+
+    // serve over a Unix domain socket when UNIX_SOCKET_PATH is set
+    #[cfg(unix)]
+    if let Some(path) = &config.unix_socket_path {
+        // a socket file left behind by a previous run would make the bind fail
+        if std::fs::metadata(path).is_ok() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| eyre!("could not bind the unix socket {}: {}", path, e))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(UNIX_SOCKET_MODE))?;
+        info!("transport: unix socket, listening on: {}", path);
+
+        http_server::serve(listener, app, settings, shutdown).await;
+
+        return Ok(());
+    }
+
+    #[cfg(not(unix))]
+    if config.unix_socket_path.is_some() {
+        warn!("UNIX_SOCKET_PATH is only supported on Unix platforms, falling back to TCP");
+    }
+
+    // spin up and listen on 127.0.0.1, port 3000 unless PORT says otherwise
+    let addr = SocketAddr::from(([127, 0, 0, 1], config.port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| bind_error(addr, e))?;
+    info!("transport: tcp, listening on port: {}", addr);
+
+    // socket options, keepalive is set on the listening socket and inherited by
+    // accepted connections, TCP_NODELAY is applied to each connection as it's accepted
+    let keepalive = match config.tcp_keepalive_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let socket = SockRef::from(&listener);
+    match keepalive {
+        Some(time) => socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?,
+        None => socket.set_keepalive(false)?,
+    }
+    info!(
+        "socket options: TCP_NODELAY={}, TCP keepalive={:?}",
+        config.tcp_nodelay, keepalive
+    );
+
+    let tcp_nodelay = config.tcp_nodelay;
+    let listener = listener.tap_io(move |tcp_stream| {
+        if let Err(e) = tcp_stream.set_nodelay(tcp_nodelay) {
+            warn!("failed to set TCP_NODELAY on an accepted connection: {}", e);
+        }
+    });
+
+    // connect info carries the peer address used by the IP allowlist
+    http_server::serve(listener, app, settings, shutdown).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use axum::body::to_bytes;
+
+    fn config() -> Arc<Config> {
+        Arc::new(Config::from_env().expect("the default configuration"))
+    }
+
+    fn page(query: &str) -> Query<PageParams> {
+        Query(serde_urlencoded::from_str(query).expect("valid read parameters"))
+    }
+
+    // the ids of the records in a read's JSON body, in the order they were sent
+    async fn ids(response: Response) -> Vec<i64> {
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("the body reads");
+        let records: Vec<serde_json::Value> =
+            serde_json::from_slice(&body).expect("the body is a JSON array");
+        records
+            .iter()
+            .map(|record| record["id"].as_i64().expect("a numeric id"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn read_returns_records_in_id_order() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, [3, 1, 2]).await;
+
+        let response = read_data(State(pool.clone()), State(config()), page(""))
+            .await
+            .expect("the read succeeds");
+        assert_eq!(ids(response).await, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn read_pages_follow_id_order() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, [3, 1, 2]).await;
+
+        let first = read_data(State(pool.clone()), State(config()), page("limit=2"))
+            .await
+            .expect("the first page reads");
+        assert_eq!(ids(first).await, [1, 2]);
+        let second = read_data(State(pool), State(config()), page("limit=2&offset=2"))
+            .await
+            .expect("the second page reads");
+        assert_eq!(ids(second).await, [3]);
+    }
+
+    #[tokio::test]
+    async fn read_pages_after_an_id() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, [5, 1, 4, 2, 3]).await;
+
+        let response = read_data(State(pool), State(config()), page("after=2&limit=2"))
+            .await
+            .expect("the read succeeds");
+        let link = response.headers()[header::LINK].clone();
+        assert_eq!(ids(response).await, [3, 4]);
+        assert_eq!(link, "</database_read?limit=2&after=4>; rel=\"next\"");
+    }
+
+    #[tokio::test]
+    async fn read_refuses_offset_and_after_together() {
+        let pool = test_support::memory_pool().await;
+        let result = read_data(State(pool), State(config()), page("offset=10&after=2")).await;
+        match result {
+            Err(AppError::BadRequest(message)) => {
+                assert!(message.contains("offset") && message.contains("after"));
+            }
+            other => panic!(
+                "expected a 400 naming both parameters, got {:?}",
+                other.err()
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn update_moves_updated_at_but_not_created_at() {
+        let pool = test_support::memory_pool().await;
+        let record = TestRecord {
+            id: 1,
+            date: NaiveDate::from_ymd_opt(2023, 1, 25).expect("a valid date"),
+            message: "first".to_string(),
+            updated_at: None,
+            created_at: None,
+        };
+        let mut conn = pool.acquire().await.expect("a connection");
+        let created = insert_record(&mut conn, &config(), &record)
+            .await
+            .expect("the record is created");
+        drop(conn);
+        assert_eq!(created.created_at, created.updated_at);
+
+        // the stamps have millisecond resolution
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let params = serde_urlencoded::from_str("id=1&date=2023-01-25&message=second")
+            .expect("valid update parameters");
+        let response = update_data(
+            State(pool.clone()),
+            State(config()),
+            Query(params),
+            HeaderMap::new(),
+        )
+        .await
+        .expect("the update succeeds");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut conn = pool.acquire().await.expect("a connection");
+        let updated = find_record(&mut conn, 1)
+            .await
+            .expect("the record reads")
+            .expect("the record exists");
+        assert_eq!(updated.message, "second");
+        assert_ne!(updated.updated_at, created.updated_at);
+        assert_eq!(updated.created_at, created.created_at);
+    }
+}
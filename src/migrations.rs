@@ -0,0 +1,190 @@
+// migrations.rs
+// the schema migrations embedded in the binary, run at startup, and "/admin/migrations"
+// (API key required) which compares them with SQLx's _sqlx_migrations table so a deployment
+// can be checked for having applied what it ships with
+
+use crate::config::Config;
+use crate::error::{self, AppError};
+use crate::sql_timeout;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use color_eyre::eyre::{eyre, Result};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::sqlite::SqlitePool;
+use sqlx::FromRow;
+use std::sync::Arc;
+use tracing::{info, instrument, warn};
+
+// the migrations in ./migrations, compiled in
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// bring the schema up to date, SQLx writes to its migrations table even when there's nothing
+// to apply, so on a read-only database that already has every migration the failure is
+// forgiven and the server starts to serve reads, with migrations pending it can't start
+pub async fn run(pool: &SqlitePool) -> Result<()> {
+    match MIGRATOR.run(pool).await {
+        Ok(()) => Ok(()),
+        Err(MigrateError::Execute(e)) if error::is_read_only(&e) => {
+            let applied: Vec<i64> =
+                sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                    .fetch_all(pool)
+                    .await?;
+            let pending = MIGRATOR
+                .iter()
+                .filter(|migration| !applied.contains(&migration.version))
+                .count();
+            if pending > 0 {
+                return Err(eyre!(
+                    "the database is read-only and {} migrations are pending, they can't be applied",
+                    pending
+                ));
+            }
+            warn!("the database is read-only, its migrations are up to date so startup continues");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// struct to hold a row of _sqlx_migrations
+#[derive(FromRow)]
+struct AppliedRow {
+    version: i64,
+    description: String,
+    installed_on: String,
+    success: bool,
+    checksum: Vec<u8>,
+    execution_time: i64,
+}
+
+// an applied migration as the route reports it, checksum_matches is false when the file in
+// the binary differs from the one that was run
+#[derive(Serialize)]
+struct Applied {
+    version: i64,
+    description: String,
+    installed_on: String,
+    success: bool,
+    checksum: String,
+    checksum_matches: bool,
+    execution_time_ms: f64,
+}
+
+// a migration in the binary which hasn't been run
+#[derive(Serialize)]
+struct Pending {
+    version: i64,
+    description: String,
+}
+
+// handler function for the route which reports applied and pending migrations
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/admin/migrations", method = "GET"))]
+pub async fn migration_status(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_one(&pool);
+    if sql_timeout::limit(&config, query).await? == 0 {
+        return Err(AppError::NotFound(
+            "the _sqlx_migrations table doesn't exist, no migrations have been run on this database"
+                .to_string(),
+        ));
+    }
+
+    let query = sqlx::query_as::<_, AppliedRow>(
+        "SELECT version, description, installed_on, success, checksum, execution_time \
+         FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(&pool);
+    let rows = sql_timeout::limit(&config, query).await?;
+
+    let applied = rows
+        .iter()
+        .map(|row| Applied {
+            version: row.version,
+            description: row.description.clone(),
+            installed_on: row.installed_on.clone(),
+            success: row.success,
+            checksum: hex(&row.checksum),
+            checksum_matches: MIGRATOR
+                .iter()
+                .find(|migration| migration.version == row.version)
+                .is_some_and(|migration| *migration.checksum == *row.checksum),
+            // execution_time is recorded in nanoseconds
+            execution_time_ms: row.execution_time as f64 / 1_000_000.0,
+        })
+        .collect::<Vec<_>>();
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| {
+            !rows
+                .iter()
+                .any(|row| row.version == migration.version && row.success)
+        })
+        .map(|migration| Pending {
+            version: migration.version,
+            description: migration.description.to_string(),
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "{} migrations applied, {} pending",
+        applied.len(),
+        pending.len()
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "up_to_date": pending.is_empty(),
+            "applied": applied,
+            "pending": pending,
+        })),
+    ))
+}
+
+// lowercase hex of a checksum
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support;
+    use sqlx::Row;
+
+    #[tokio::test]
+    async fn migrations_index_the_date_and_message_lookups() {
+        let pool = test_support::memory_pool().await;
+
+        let indexes: Vec<String> = sqlx::query("PRAGMA index_list(test)")
+            .fetch_all(&pool)
+            .await
+            .expect("the indexes list")
+            .iter()
+            .map(|row| row.get("name"))
+            .collect();
+        for index in ["test_date", "test_message_nocase"] {
+            assert!(
+                indexes.iter().any(|name| name == index),
+                "{} is missing from {:?}",
+                index,
+                indexes
+            );
+        }
+
+        // the autocomplete prefix match can only use the message index if it ignores case,
+        // as LIKE does
+        let collation: String = sqlx::query_scalar(
+            "SELECT coll FROM pragma_index_xinfo('test_message_nocase') WHERE key AND name = 'message'",
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("the index has a message column");
+        assert_eq!(collation, "NOCASE");
+    }
+}
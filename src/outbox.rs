@@ -0,0 +1,202 @@
+// outbox.rs
+// at-least-once webhook delivery through an outbox table, events are written on the same
+// transaction as the change they describe and a background worker POSTs them to WEBHOOK_URL,
+// failed deliveries are retried with a doubling backoff until WEBHOOK_MAX_ATTEMPTS is reached,
+// after which the event is left in the 'dead' state for an operator to look at
+
+use crate::config::Config;
+use crate::TestRecord;
+use serde_json::json;
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
+use sqlx::FromRow;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+// the kinds of event written to the outbox
+pub const RECORD_CREATED: &str = "record.created";
+
+// delivery states of an outbox entry
+const PENDING: &str = "pending";
+const SENT: &str = "sent";
+const DEAD: &str = "dead";
+
+// most entries picked up by one pass of the worker
+const BATCH_SIZE: i64 = 10;
+
+// longest a single delivery may take before it counts as failed
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// struct to hold an entry waiting to be delivered
+#[derive(Debug, FromRow)]
+struct OutboxEntry {
+    id: i64,
+    event: String,
+    payload: String,
+    attempts: i64,
+}
+
+// queue a record event, meant to run on the same transaction as the change it describes
+// nothing is written when no WEBHOOK_URL is configured
+pub async fn enqueue(
+    conn: &mut SqliteConnection,
+    config: &Config,
+    event: &str,
+    record: &TestRecord,
+) -> Result<(), sqlx::Error> {
+    if config.webhook_url.is_none() {
+        return Ok(());
+    }
+
+    let payload = json!({ "event": event, "record": record });
+    sqlx::query("INSERT INTO outbox (event, payload) VALUES ($1, $2)")
+        .bind(event)
+        .bind(payload.to_string())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+// deliver pending outbox entries until shutdown is signalled, polling every
+// OUTBOX_POLL_INTERVAL_MS, a delivery under way when the signal arrives is finished first
+pub async fn deliver_events(
+    pool: SqlitePool,
+    config: Arc<Config>,
+    url: String,
+    shutdown: CancellationToken,
+) {
+    let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!(
+                "could not build the webhook client, events won't be delivered: {}",
+                e
+            );
+            return;
+        }
+    };
+    let poll_interval = Duration::from_millis(config.outbox_poll_interval_ms);
+
+    while !shutdown.is_cancelled() {
+        match deliver_batch(&pool, &config, &client, &url, &shutdown).await {
+            // a full batch means there may be more waiting, go again straight away
+            Ok(attempted) if attempted as i64 == BATCH_SIZE => continue,
+            Ok(_) => {}
+            Err(e) => error!("could not read the outbox: {}", e),
+        }
+
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(poll_interval) => {}
+        }
+    }
+    info!("outbox worker stopped");
+}
+
+// attempt every entry that's due, returning how many were attempted
+async fn deliver_batch(
+    pool: &SqlitePool,
+    config: &Config,
+    client: &reqwest::Client,
+    url: &str,
+    shutdown: &CancellationToken,
+) -> Result<usize, sqlx::Error> {
+    let entries = sqlx::query_as::<_, OutboxEntry>(
+        "SELECT id, event, payload, attempts FROM outbox \
+         WHERE status = $1 AND next_attempt_at <= CAST(strftime('%s', 'now') AS INTEGER) \
+         ORDER BY id LIMIT $2",
+    )
+    .bind(PENDING)
+    .bind(BATCH_SIZE)
+    .fetch_all(pool)
+    .await?;
+
+    let mut attempted = 0;
+    for entry in entries {
+        if shutdown.is_cancelled() {
+            break;
+        }
+        attempted += 1;
+
+        match post(client, url, &entry).await {
+            Ok(()) => {
+                sqlx::query("UPDATE outbox SET status = $1, attempts = attempts + 1 WHERE id = $2")
+                    .bind(SENT)
+                    .bind(entry.id)
+                    .execute(pool)
+                    .await?;
+                info!("delivered {} event {}", entry.event, entry.id);
+            }
+            Err(reason) => {
+                let attempts = entry.attempts + 1;
+                if attempts >= i64::from(config.webhook_max_attempts) {
+                    error!(
+                        "giving up on {} event {} after {} attempts: {}",
+                        entry.event, entry.id, attempts, reason
+                    );
+                    sqlx::query(
+                        "UPDATE outbox SET status = $1, attempts = $2, last_error = $3 WHERE id = $4",
+                    )
+                    .bind(DEAD)
+                    .bind(attempts)
+                    .bind(&reason)
+                    .bind(entry.id)
+                    .execute(pool)
+                    .await?;
+                } else {
+                    let backoff = retry_backoff(config, attempts);
+                    warn!(
+                        "delivery of {} event {} failed, retrying in {}s (attempt {} of {}): {}",
+                        entry.event,
+                        entry.id,
+                        backoff,
+                        attempts,
+                        config.webhook_max_attempts,
+                        reason
+                    );
+                    sqlx::query(
+                        "UPDATE outbox SET attempts = $1, last_error = $2, \
+                         next_attempt_at = CAST(strftime('%s', 'now') AS INTEGER) + $3 WHERE id = $4",
+                    )
+                    .bind(attempts)
+                    .bind(&reason)
+                    .bind(backoff)
+                    .bind(entry.id)
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+    }
+
+    Ok(attempted)
+}
+
+// POST an entry's payload to the webhook, any non-2xx response is a failure
+async fn post(client: &reqwest::Client, url: &str, entry: &OutboxEntry) -> Result<(), String> {
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("x-webhook-event", &entry.event)
+        .header("x-webhook-id", entry.id.to_string())
+        .body(entry.payload.clone())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("webhook responded {}", response.status()))
+    }
+}
+
+// seconds to wait before the next attempt, doubling after every failure
+fn retry_backoff(config: &Config, attempts: i64) -> i64 {
+    let doublings = u32::try_from(attempts - 1).unwrap_or(0).min(16);
+    i64::try_from(config.webhook_retry_backoff_secs)
+        .unwrap_or(i64::MAX)
+        .saturating_mul(1 << doublings)
+}
@@ -0,0 +1,182 @@
+// patch.rs
+// partial updates of a single record through PATCH /database/{id}
+// a body sent as application/json-patch+json is an RFC 6902 JSON Patch applied to the record's
+// JSON form, a plain application/json body lists just the fields to change, either way only
+// date and message can be changed and the id stays as it is
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::{audit, compress, etag, find_record, retry, server_timing, updated_at_now, TestRecord};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use chrono::NaiveDate;
+use json_patch::{Patch, PatchOperation};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+// media type for RFC 6902 JSON Patch documents
+const JSON_PATCH: &str = "application/json-patch+json";
+
+// fields a patch may change, a test operation may also check the id
+const MUTABLE_FIELDS: &[&str] = &["/date", "/message"];
+const TESTABLE_FIELDS: &[&str] = &["/id", "/date", "/message"];
+
+// struct to hold a simple partial update, fields left out keep their current values
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RecordChanges {
+    date: Option<NaiveDate>,
+    message: Option<String>,
+}
+
+// the two forms of change a PATCH request can carry
+enum Change {
+    JsonPatch(Patch),
+    Fields(RecordChanges),
+}
+
+// handler function for the route which applies a partial update to a record
+// the updated record is returned
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database/{id}", method = "PATCH", id = id))]
+pub async fn patch_record(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let change = parse_change(&headers, &body)?;
+    etag::require(&config, &headers)?;
+
+    let (pool, config, change, headers) = (&pool, &config, &change, &headers);
+    let record = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let before = find_record(&mut tx, id).await?;
+        etag::check(headers, before.as_ref())?;
+        let before =
+            before.ok_or_else(|| AppError::NotFound(format!("no record with id {}", id)))?;
+        let after = apply(&before, change)?;
+
+        let message = compress::store(config, &after.message);
+        let after = sqlx::query_as::<_, TestRecord>(
+            "UPDATE test SET date = $1, message = $2, message_zstd = $3, updated_at = $4 \
+             WHERE id = $5 RETURNING *",
+        )
+        .bind(after.date)
+        .bind(message.text)
+        .bind(message.zstd)
+        .bind(updated_at_now())
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record_change(&mut tx, id, audit::UPDATE, Some(&before)).await?;
+        tx.commit().await?;
+        Ok(after)
+    })
+    .await?;
+    info!("record patched");
+
+    Ok((
+        StatusCode::OK,
+        [etag::header(&record)],
+        server_timing::json(record),
+    ))
+}
+
+// read the change from the body according to its Content-Type
+fn parse_change(headers: &HeaderMap, body: &[u8]) -> Result<Change, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase());
+
+    match content_type.as_deref() {
+        Some(JSON_PATCH) => {
+            let patch: Patch = serde_json::from_slice(body)
+                .map_err(|e| AppError::BadRequest(format!("invalid JSON Patch: {}", e)))?;
+            check_paths(&patch)?;
+            Ok(Change::JsonPatch(patch))
+        }
+        Some("application/json") => serde_json::from_slice(body)
+            .map(Change::Fields)
+            .map_err(|e| AppError::BadRequest(format!("invalid update: {}", e))),
+        _ => Err(AppError::UnsupportedMediaType(format!(
+            "expected Content-Type application/json or {}",
+            JSON_PATCH
+        ))),
+    }
+}
+
+// reject operations that touch the id or a field the record doesn't have
+fn check_paths(patch: &Patch) -> Result<(), AppError> {
+    for operation in &patch.0 {
+        let (allowed, from) = match operation {
+            PatchOperation::Test(_) => (TESTABLE_FIELDS, None),
+            PatchOperation::Move(op) => (MUTABLE_FIELDS, Some(op.from.as_str())),
+            PatchOperation::Copy(op) => (MUTABLE_FIELDS, Some(op.from.as_str())),
+            _ => (MUTABLE_FIELDS, None),
+        };
+        let path = operation.path().as_str();
+        if !allowed.contains(&path) {
+            return Err(AppError::BadRequest(format!(
+                "JSON Patch may not change {:?}, only /date and /message can be changed",
+                path
+            )));
+        }
+        if let Some(from) = from.filter(|from| !TESTABLE_FIELDS.contains(from)) {
+            return Err(AppError::BadRequest(format!(
+                "JSON Patch may not read from unknown field {:?}",
+                from
+            )));
+        }
+    }
+    Ok(())
+}
+
+// work out the record after the change, the message must still be a non-empty string and
+// the date a YYYY-MM-DD date, which deserializing the patched record checks
+fn apply(before: &TestRecord, change: &Change) -> Result<TestRecord, AppError> {
+    let after = match change {
+        Change::JsonPatch(patch) => {
+            let mut document =
+                serde_json::to_value(before).expect("a record always serializes to JSON");
+            json_patch::patch(&mut document, &patch.0)
+                .map_err(|e| AppError::BadRequest(format!("could not apply JSON Patch: {}", e)))?;
+            patched_record(document)?
+        }
+        Change::Fields(changes) => TestRecord {
+            id: before.id,
+            date: changes.date.unwrap_or(before.date),
+            message: changes
+                .message
+                .clone()
+                .unwrap_or_else(|| before.message.clone()),
+            updated_at: None,
+            created_at: None,
+        },
+    };
+
+    if after.message.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "message must not be empty".to_string(),
+        ));
+    }
+    Ok(after)
+}
+
+// turn the patched document back into a record, a removed field, a non-string value or a date
+// that isn't YYYY-MM-DD fails
+fn patched_record(document: Value) -> Result<TestRecord, AppError> {
+    serde_json::from_value(document)
+        .map_err(|e| AppError::BadRequest(format!("patched record is invalid: {}", e)))
+}
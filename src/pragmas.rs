@@ -0,0 +1,47 @@
+// pragmas.rs
+// SQLite tuning from SQLITE_PRAGMAS, such as cache_size or synchronous, applied to every
+// connection the pool opens from its after_connect hook, since most pragmas only last for the
+// connection that ran them
+// the names are checked against an allow-list and the values must be integers or keywords when
+// the configuration is read, so the statements built here can't run anything else
+
+use crate::config::SqlitePragma;
+use sqlx::sqlite::{SqliteConnection, SqlitePool};
+use tracing::{debug, info, warn};
+
+// run the pragmas on a new connection, in the order they were configured
+pub async fn apply(
+    conn: &mut SqliteConnection,
+    pragmas: &[SqlitePragma],
+) -> Result<(), sqlx::Error> {
+    for pragma in pragmas {
+        let sql = format!("PRAGMA {} = {}", pragma.name, pragma.value);
+        sqlx::query(&sql)
+            .persistent(false)
+            .execute(&mut *conn)
+            .await?;
+        debug!("applied PRAGMA {}", pragma);
+    }
+    Ok(())
+}
+
+// log the value each pragma has on a pooled connection, SQLite ignores or clamps some settings
+// (mmap_size is capped at its compile-time limit) so this can differ from what was configured
+pub async fn log_effective(pool: &SqlitePool, pragmas: &[SqlitePragma]) {
+    if pragmas.is_empty() {
+        return;
+    }
+    let mut effective = Vec::with_capacity(pragmas.len());
+    for pragma in pragmas {
+        let sql = format!("PRAGMA {}", pragma.name);
+        match sqlx::query_scalar::<_, i64>(&sql)
+            .persistent(false)
+            .fetch_one(pool)
+            .await
+        {
+            Ok(value) => effective.push(format!("{}={}", pragma.name, value)),
+            Err(e) => warn!("could not read back PRAGMA {}: {}", pragma.name, e),
+        }
+    }
+    info!("SQLite pragmas applied: {}", effective.join(", "));
+}
@@ -0,0 +1,43 @@
+// queries.rs
+// the SQL behind the record routes whose speed matters most, shared with benches/crud.rs so the
+// benchmarks time the statements the handlers run, it uses nothing from the crate so the bench
+// can include it as it stands
+
+// a record by its id, for /database_search, /database/{id} and the before-images of writes
+pub const RECORD_BY_ID: &str = "SELECT * FROM test WHERE id = $1";
+
+// a new record, binding its id, date, message, compressed message and the time it's stored,
+// which is both its updated_at and its created_at
+pub const INSERT_RECORD: &str = "INSERT INTO test \
+     (id, date, message, message_zstd, updated_at, created_at) \
+     VALUES ($1, $2, $3, $4, $5, $5) RETURNING *";
+
+// /database_autocomplete's prefix match, binding the pattern and the limit, SQLite only uses the
+// NOCASE index on message for a LIKE without an ESCAPE clause, so only a prefix with wildcards in
+// it takes the escaped one
+pub const AUTOCOMPLETE: &str = "SELECT DISTINCT message FROM test WHERE message LIKE $1 \
+     ORDER BY message COLLATE NOCASE LIMIT $2";
+pub const AUTOCOMPLETE_ESCAPED: &str = "SELECT DISTINCT message FROM test \
+     WHERE message LIKE $1 ESCAPE '\\' ORDER BY message COLLATE NOCASE LIMIT $2";
+
+// /database_changes' keyset page, binding since, the id after which records written at since
+// continue and the limit, reservations aren't records yet so they're left out
+pub const CHANGES: &str = "SELECT * FROM test WHERE reserved_until IS NULL \
+     AND (updated_at > $1 OR (updated_at = $1 AND id > $2)) \
+     ORDER BY updated_at, id LIMIT $3";
+
+// the number of records matching a where clause as Filter::where_clause builds it
+pub fn count(where_clause: &str) -> String {
+    format!("SELECT COUNT(*) FROM test{}", where_clause)
+}
+
+// a page of the records matching a where clause in id order, the limit and offset are bound
+// to placeholders next and next + 1, after the filter's own
+pub fn page(where_clause: &str, next: usize) -> String {
+    format!(
+        "SELECT * FROM test{} ORDER BY id LIMIT ${} OFFSET ${}",
+        where_clause,
+        next,
+        next + 1
+    )
+}
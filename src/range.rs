@@ -0,0 +1,186 @@
+// range.rs
+// Range requests on the /database_stream export, so an interrupted download can be resumed
+// the streamed export has no length and changes as records are written, so a request with a
+// Range header is answered from a snapshot instead, the export is written to a temporary file
+// and hashed for its ETag on the way, the requested bytes are sent from the file and it's removed
+// once the response is done with it
+// a single "bytes=" range is answered with a 206 and Content-Range and a range that starts past
+// the end with a 416, several ranges, a malformed header or an If-Range that doesn't match the
+// snapshot's ETag get the whole snapshot with a 200, as RFC 9110 allows
+
+use crate::error::AppError;
+use crate::{etag, stream, TestRecord};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::stream::{StreamExt, TryStreamExt};
+use sqlx::sqlite::SqlitePool;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{env, fs, process};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio_util::io::ReaderStream;
+use tracing::{info, warn};
+
+// numbers the snapshots so concurrent requests write to different files
+static SNAPSHOTS: AtomicU64 = AtomicU64::new(0);
+
+// struct to hold an export written to a temporary file, the file is removed when it's dropped
+struct Snapshot {
+    path: PathBuf,
+    len: u64,
+    etag: String,
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            if e.kind() != ErrorKind::NotFound {
+                warn!("could not remove export snapshot {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+// the part of the snapshot a request asked for, the byte positions are inclusive
+enum Requested {
+    Whole,
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+// answer a Range request on the export from a fresh snapshot
+pub async fn export(pool: &SqlitePool, headers: &HeaderMap) -> Result<Response, AppError> {
+    let snapshot = write_snapshot(pool).await?;
+    let (status, first, last) = match requested(headers, &snapshot) {
+        Requested::Whole => (StatusCode::OK, 0, snapshot.len.saturating_sub(1)),
+        Requested::Part(first, last) => (StatusCode::PARTIAL_CONTENT, first, last),
+        Requested::Unsatisfiable => return Err(AppError::RangeNotSatisfiable(snapshot.len)),
+    };
+    let length = if snapshot.len == 0 {
+        0
+    } else {
+        last - first + 1
+    };
+    info!(
+        "sending bytes {}-{} of a {} byte export",
+        first, last, snapshot.len
+    );
+
+    let mut file = File::open(&snapshot.path).await.map_err(AppError::Io)?;
+    file.seek(SeekFrom::Start(first))
+        .await
+        .map_err(AppError::Io)?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(stream::NDJSON),
+    );
+    response_headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    response_headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    response_headers.insert(
+        header::ETAG,
+        HeaderValue::from_str(&snapshot.etag).expect("an ETag is always a valid header value"),
+    );
+    if status == StatusCode::PARTIAL_CONTENT {
+        let content_range = format!("bytes {}-{}/{}", first, last, snapshot.len);
+        response_headers.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).expect("a Content-Range is a valid header value"),
+        );
+    }
+
+    // the snapshot travels with the body so the file is only removed once it has been sent
+    let body = ReaderStream::new(file.take(length)).map(move |chunk| {
+        let _ = &snapshot;
+        chunk
+    });
+    Ok((status, response_headers, Body::from_stream(body)).into_response())
+}
+
+// write the export to a new temporary file, exactly as /database_stream would send it
+async fn write_snapshot(pool: &SqlitePool) -> Result<Snapshot, AppError> {
+    let path = env::temp_dir().join(format!(
+        "axum-api-dbase-export-{}-{}.ndjson",
+        process::id(),
+        SNAPSHOTS.fetch_add(1, Ordering::Relaxed)
+    ));
+    // held from the start so a failed export doesn't leave a partial file behind
+    let mut snapshot = Snapshot {
+        path,
+        len: 0,
+        etag: String::new(),
+    };
+    let mut file = BufWriter::new(File::create(&snapshot.path).await.map_err(AppError::Io)?);
+    let mut hasher = etag::Hasher::new();
+    let mut rows = sqlx::query_as::<_, TestRecord>(stream::EXPORT_SQL).fetch(pool);
+    let mut line = Vec::new();
+    while let Some(record) = rows.try_next().await? {
+        line.clear();
+        stream::write_line(&mut line, &record);
+        hasher.update(&line);
+        file.write_all(&line).await.map_err(AppError::Io)?;
+        snapshot.len += line.len() as u64;
+    }
+    file.flush().await.map_err(AppError::Io)?;
+    snapshot.etag = hasher.tag();
+    Ok(snapshot)
+}
+
+// work out which bytes of the snapshot the Range and If-Range headers ask for
+fn requested(headers: &HeaderMap, snapshot: &Snapshot) -> Requested {
+    // a client resuming with a tag from an export that has since changed gets it all again,
+    // a date never matches as the snapshot has no modification time
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        if if_range.to_str().ok().map(str::trim) != Some(snapshot.etag.as_str()) {
+            return Requested::Whole;
+        }
+    }
+    let Some(range) = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+    else {
+        return Requested::Whole;
+    };
+    if range.contains(',') {
+        return Requested::Whole;
+    }
+    let Some((first, last)) = range.split_once('-') else {
+        return Requested::Whole;
+    };
+
+    let len = snapshot.len;
+    match (first.trim(), last.trim()) {
+        ("", "") => Requested::Whole,
+        // "bytes=-500" asks for the last 500 bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => Requested::Unsatisfiable,
+            Ok(_) if len == 0 => Requested::Unsatisfiable,
+            Ok(suffix) => Requested::Part(len - suffix.min(len), len - 1),
+            Err(_) => Requested::Whole,
+        },
+        (first, last) => {
+            let Ok(first) = first.parse::<u64>() else {
+                return Requested::Whole;
+            };
+            let last = match last {
+                "" => u64::MAX,
+                last => match last.parse::<u64>() {
+                    Ok(last) if last >= first => last,
+                    _ => return Requested::Whole,
+                },
+            };
+            if first >= len {
+                Requested::Unsatisfiable
+            } else {
+                Requested::Part(first, last.min(len - 1))
+            }
+        }
+    }
+}
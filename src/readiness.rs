@@ -0,0 +1,110 @@
+// readiness.rs
+// readiness probe, "/readyz" checks that the database answers and that the filesystem holding
+// the SQLite file has at least MIN_DISK_FREE_MB free, so a full disk shows up before writes
+// start failing, either problem reports "degraded" with a 503, while the migrations are still
+// running at startup it reports "starting" with a 503
+
+use crate::config::Config;
+use crate::sql_timeout;
+use crate::startup::StartingUp;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{instrument, warn};
+
+// a single-connection pool kept apart from the one serving requests, so the probe still reaches
+// the database when request traffic has every pooled connection checked out
+#[derive(Clone, Debug)]
+pub struct HealthPool(pub SqlitePool);
+
+// bytes in a megabyte, as MIN_DISK_FREE_MB counts them
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+// handler function for the readiness route
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/readyz", method = "GET"))]
+pub async fn readyz(
+    State(HealthPool(pool)): State<HealthPool>,
+    State(config): State<Arc<Config>>,
+    State(starting_up): State<StartingUp>,
+) -> impl IntoResponse {
+    if starting_up.is_starting() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "starting" })),
+        );
+    }
+
+    let query = sqlx::query("SELECT 1").execute(&pool);
+    let database_ok = match sql_timeout::limit(&config, query).await {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("readiness check could not reach the database: {:?}", e);
+            false
+        }
+    };
+
+    // a platform without a free space check skips it rather than failing the probe
+    let disk_free_mb = match available_space(Path::new(config.database_file())) {
+        Ok(bytes) => Some(bytes / BYTES_PER_MB),
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => None,
+        Err(e) => {
+            warn!("readiness check could not read free disk space: {}", e);
+            Some(0)
+        }
+    };
+    let disk_ok = config.min_disk_free_mb == 0
+        || disk_free_mb.is_none_or(|free| free >= config.min_disk_free_mb);
+    if !disk_ok {
+        warn!(
+            "free disk space {} MB is below MIN_DISK_FREE_MB ({})",
+            disk_free_mb.unwrap_or_default(),
+            config.min_disk_free_mb
+        );
+    }
+
+    let (status, label) = if database_ok && disk_ok {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    };
+    (
+        status,
+        Json(json!({
+            "status": label,
+            "database": if database_ok { "ok" } else { "unavailable" },
+            "disk_free_mb": disk_free_mb,
+            "min_disk_free_mb": config.min_disk_free_mb,
+        })),
+    )
+}
+
+// space available to unprivileged users on the filesystem holding path, in bytes
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: statvfs only writes into the struct we pass, and the path is NUL terminated
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free disk space is only checked on Unix platforms",
+    ))
+}
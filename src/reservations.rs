@@ -0,0 +1,182 @@
+// reservations.rs
+// ids reserved ahead of their data, for workflows that need a record's id before they can fill
+// it in, POST /database_reserve stores a placeholder row with an empty message and a
+// reserved_until time RESERVATION_TTL_SECS ahead and returns its id, PUT /database_confirm/{id}
+// fills in the date and message and clears reserved_until, making it an ordinary record
+// a reservation that isn't confirmed in time lapses, it can no longer be confirmed and the
+// sweeper deletes it every RESERVATION_SWEEP_INTERVAL_SECS
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::{Path, RequiredJson};
+use crate::id_format::Id;
+use crate::redact::RedactedMessage;
+use crate::{audit, check_quota, compress, find_record, outbox, retry, updated_at_now, TestRecord};
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
+};
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, instrument};
+
+// the current time in the format reserved_until is stored in, so the two compare as text
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%SZ', 'now')";
+
+// struct to hold the data a reservation is confirmed with, a missing date defaults to today
+#[derive(Deserialize)]
+pub struct Confirmation {
+    date: Option<NaiveDate>,
+    message: String,
+}
+
+impl fmt::Debug for Confirmation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Confirmation")
+            .field("date", &self.date)
+            .field("message", &RedactedMessage(&self.message))
+            .finish()
+    }
+}
+
+// handler function for the route which reserves an id, answering with the id and the time the
+// reservation lapses
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_reserve", method = "POST"))]
+pub async fn reserve(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let (pool, config) = (&pool, &config);
+    let (id, reserved_until) = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        check_quota(&mut tx, config).await?;
+        let (id, reserved_until): (i32, String) = sqlx::query_as(
+            "INSERT INTO test (date, message, reserved_until) \
+             VALUES ($1, '', strftime('%Y-%m-%dT%H:%M:%SZ', 'now', $2)) \
+             RETURNING id, reserved_until",
+        )
+        .bind(Utc::now().date_naive())
+        .bind(format!("+{} seconds", config.reservation_ttl_secs))
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record_change(&mut tx, id, audit::CREATE, None).await?;
+        tx.commit().await?;
+        Ok((id, reserved_until))
+    })
+    .await?;
+    info!("reserved id {} until {}", id, reserved_until);
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/database_confirm/{}", id))],
+        Json(json!({ "id": Id(id), "reserved_until": reserved_until })),
+    ))
+}
+
+// handler function for the route which turns a reservation into a record, a lapsed or unknown
+// reservation is a 404 and a record that was never reserved or is already confirmed a 409
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_confirm/{id}", method = "PUT", id = id))]
+pub async fn confirm(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(id): Path<i32>,
+    RequiredJson(confirmation): RequiredJson<Confirmation>,
+) -> Result<impl IntoResponse, AppError> {
+    debug!("confirming reservation: {:?}", confirmation);
+    if confirmation.message.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "message must not be empty".to_string(),
+        ));
+    }
+    let date = confirmation.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    let (pool, config, date, message) = (&pool, &config, &date, &confirmation.message);
+    let record = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        let sql = format!(
+            "SELECT reserved_until IS NOT NULL, reserved_until > {} FROM test WHERE id = $1",
+            NOW
+        );
+        let state: Option<(bool, Option<bool>)> = sqlx::query_as(&sql)
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        match state {
+            None | Some((true, Some(false))) => {
+                return Err(AppError::NotFound(format!(
+                    "no reservation of id {}, it may have lapsed",
+                    id
+                )))
+            }
+            Some((false, _)) => {
+                return Err(AppError::Conflict(format!(
+                    "record {} isn't reserved, it may already be confirmed",
+                    id
+                )))
+            }
+            _ => {}
+        }
+
+        let before = find_record(&mut tx, id).await?;
+        let stored = compress::store(config, message);
+        let record = sqlx::query_as::<_, TestRecord>(
+            "UPDATE test SET date = $1, message = $2, message_zstd = $3, reserved_until = NULL, \
+             updated_at = $4 WHERE id = $5 RETURNING *",
+        )
+        .bind(date)
+        .bind(stored.text)
+        .bind(stored.zstd)
+        .bind(updated_at_now())
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        audit::record_change(&mut tx, id, audit::UPDATE, before.as_ref()).await?;
+        outbox::enqueue(&mut tx, config, outbox::RECORD_CREATED, &record).await?;
+        tx.commit().await?;
+        Ok(record)
+    })
+    .await?;
+    info!("reservation confirmed");
+
+    Ok((
+        StatusCode::OK,
+        [(header::LOCATION, format!("/database/{}", record.id))],
+        Json(record),
+    ))
+}
+
+// delete lapsed reservations every period until shutdown is signalled
+pub async fn sweep_periodically(pool: SqlitePool, period: Duration, shutdown: CancellationToken) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                let sql = format!(
+                    "DELETE FROM test WHERE reserved_until IS NOT NULL AND reserved_until <= {}",
+                    NOW
+                );
+                match sqlx::query(&sql).execute(&pool).await {
+                    Ok(result) if result.rows_affected() > 0 => {
+                        info!("swept {} lapsed reservations", result.rows_affected())
+                    }
+                    Ok(_) => {}
+                    Err(e) => error!("sweeping lapsed reservations failed: {}", e),
+                }
+            }
+        }
+    }
+    info!("reservation sweeper stopped");
+}
@@ -0,0 +1,184 @@
+// retry.rs
+// retries database work that failed because SQLite was busy (SQLITE_BUSY)
+// any other error is returned straight away
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::sql_timeout;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+// primary result code for SQLITE_BUSY, extended codes keep it in the low byte
+const SQLITE_BUSY: i32 = 5;
+
+// run a unit of database work, typically a whole transaction, retrying it when SQLite
+// reports the database as busy, the wait doubles after every attempt, each attempt is
+// bounded by the SQL timeout
+pub async fn on_busy<T, F, Fut>(config: &Config, mut operation: F) -> Result<T, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    let mut backoff = Duration::from_millis(config.busy_retry_backoff_ms);
+    let mut attempt = 0;
+
+    loop {
+        match sql_timeout::limit(config, operation()).await {
+            Err(e) if is_busy(&e) && attempt < config.busy_retries => {
+                attempt += 1;
+                warn!(
+                    "database busy, retrying in {:?} (attempt {} of {})",
+                    backoff, attempt, config.busy_retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+// check whether an error is SQLite's SQLITE_BUSY or one of its extended codes
+fn is_busy(e: &AppError) -> bool {
+    match e {
+        AppError::Database(sqlx::Error::Database(db_error)) => db_error
+            .code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| code & 0xff == SQLITE_BUSY),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+    use sqlx::{Connection, SqliteConnection};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::{env, fs, process};
+
+    // a database file with a pool and a second connection on it, neither waits in SQLite's busy
+    // handler so contention surfaces as SQLITE_BUSY straight away
+    async fn contended(name: &str) -> (SqlitePool, SqliteConnection, PathBuf) {
+        let path = env::temp_dir().join(format!(
+            "axum-api-dbase-retry-{}-{}.db",
+            process::id(),
+            name
+        ));
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .busy_timeout(Duration::ZERO);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options.clone())
+            .await
+            .expect("the database opens");
+        sqlx::query("CREATE TABLE counter (value INTEGER NOT NULL)")
+            .execute(&pool)
+            .await
+            .expect("the table is created");
+        let holder = SqliteConnection::connect_with(&options)
+            .await
+            .expect("a second connection opens");
+        (pool, holder, path)
+    }
+
+    async fn remove(pool: SqlitePool, path: PathBuf) {
+        pool.close().await;
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn config(busy_retries: u32) -> Config {
+        Config {
+            busy_retries,
+            busy_retry_backoff_ms: 10,
+            ..Config::from_env().expect("the default configuration")
+        }
+    }
+
+    #[tokio::test]
+    async fn busy_is_retried_until_the_lock_is_released() {
+        let (pool, mut holder, path) = contended("released").await;
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut holder)
+            .await
+            .expect("the write lock is taken");
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            sqlx::query("COMMIT")
+                .execute(&mut holder)
+                .await
+                .expect("the write lock is released");
+        });
+
+        let attempts = AtomicU32::new(0);
+        let result = on_busy(&config(10), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            sqlx::query("INSERT INTO counter (value) VALUES (1)")
+                .execute(&pool)
+                .await?;
+            Ok(())
+        })
+        .await;
+        release.await.expect("the holder finishes");
+
+        assert!(result.is_ok(), "the write succeeds once the lock is gone");
+        assert!(
+            attempts.load(Ordering::SeqCst) > 1,
+            "the busy write was retried"
+        );
+        remove(pool, path).await;
+    }
+
+    #[tokio::test]
+    async fn busy_gives_up_after_the_configured_retries() {
+        let (pool, mut holder, path) = contended("held").await;
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut holder)
+            .await
+            .expect("the write lock is taken");
+
+        let attempts = AtomicU32::new(0);
+        let result = on_busy(&config(2), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            sqlx::query("INSERT INTO counter (value) VALUES (1)")
+                .execute(&pool)
+                .await?;
+            Ok(())
+        })
+        .await;
+
+        assert!(
+            result.as_ref().is_err_and(is_busy),
+            "the busy error is returned"
+        );
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        holder.close().await.expect("the holder closes");
+        remove(pool, path).await;
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let (pool, holder, path) = contended("other").await;
+
+        let attempts = AtomicU32::new(0);
+        let result = on_busy(&config(10), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            sqlx::query("INSERT INTO missing (value) VALUES (1)")
+                .execute(&pool)
+                .await?;
+            Ok(())
+        })
+        .await;
+
+        assert!(matches!(result, Err(AppError::Database(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+        holder.close().await.expect("the holder closes");
+        remove(pool, path).await;
+    }
+}
@@ -0,0 +1,107 @@
+// server_timing.rs
+// Server-Timing response header for performance debugging, enabled with SERVER_TIMING
+// time spent in the database and serializing the response is collected in a task-local
+// while the request is handled and reported as e.g. "db;dur=12.3, serialize;dur=1.1, total;dur=14.0"
+// the same timings feed the slowest requests list when SLOWEST_REQUESTS is set
+
+use crate::config::Config;
+use crate::slowest::SlowestRequests;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// name of the header carrying the timings
+static SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+// phases reported in the header
+pub const DB: &str = "db";
+pub const SERIALIZE: &str = "serialize";
+
+tokio::task_local! {
+    // phases timed so far for the current request, only set when SERVER_TIMING or
+    // SLOWEST_REQUESTS is on
+    static TIMINGS: RefCell<Vec<(&'static str, Duration)>>;
+}
+
+// add time spent in a phase to the current request, repeated phases are summed
+// does nothing outside a request or when neither SERVER_TIMING nor SLOWEST_REQUESTS is on
+pub fn record(phase: &'static str, elapsed: Duration) {
+    let _ = TIMINGS.try_with(|timings| {
+        let mut timings = timings.borrow_mut();
+        match timings.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => timings.push((phase, elapsed)),
+        }
+    });
+}
+
+// build a JSON response, timing the serialization
+pub fn json<T: Serialize>(value: T) -> Response {
+    let start = Instant::now();
+    let response = Json(value).into_response();
+    record(SERIALIZE, start.elapsed());
+    response
+}
+
+// middleware function which collects the timings of a request, adds the header and offers
+// the request to the slowest requests list
+pub async fn add_server_timing(
+    State(config): State<Arc<Config>>,
+    State(slowest): State<Arc<SlowestRequests>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !config.server_timing && config.slowest_requests == 0 {
+        return next.run(request).await;
+    }
+
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    TIMINGS
+        .scope(RefCell::new(Vec::new()), async move {
+            let start = Instant::now();
+            let mut response = next.run(request).await;
+            let total = start.elapsed();
+
+            if config.slowest_requests > 0 {
+                let db = TIMINGS.with(|timings| {
+                    timings
+                        .borrow()
+                        .iter()
+                        .find(|(name, _)| *name == DB)
+                        .map_or(Duration::ZERO, |(_, elapsed)| *elapsed)
+                });
+                slowest.record(config.slowest_requests, &method, &path, total, db);
+            }
+            if !config.server_timing {
+                return response;
+            }
+
+            let header = TIMINGS.with(|timings| {
+                timings
+                    .borrow()
+                    .iter()
+                    .chain([&("total", total)])
+                    .map(|(name, elapsed)| format!("{};dur={:.1}", name, as_millis(*elapsed)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            });
+            if let Ok(value) = HeaderValue::from_str(&header) {
+                response.headers_mut().insert(SERVER_TIMING.clone(), value);
+            }
+            response
+        })
+        .await
+}
+
+// durations are reported in fractional milliseconds
+fn as_millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,68 @@
+// slowest.rs
+// a built-in lightweight profiler, the server-timing middleware hands every finished request
+// to SlowestRequests which keeps the SLOWEST_REQUESTS slowest ones seen since startup,
+// GET /admin/slowest (API key required) lists them slowest first
+
+use crate::config::Config;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::instrument;
+
+// one profiled request, durations are in fractional milliseconds
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowRequest {
+    pub method: String,
+    pub path: String,
+    pub total_ms: f64,
+    pub db_ms: f64,
+}
+
+// struct to hold the slowest requests, kept sorted slowest first
+#[derive(Debug, Default)]
+pub struct SlowestRequests {
+    requests: Mutex<Vec<SlowRequest>>,
+}
+
+impl SlowestRequests {
+    // offer a finished request, it's kept if it's among the capacity slowest so far
+    // the capacity is read per call so a reload shrinks or grows the list
+    pub fn record(&self, capacity: usize, method: &str, path: &str, total: Duration, db: Duration) {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        let total_ms = total.as_secs_f64() * 1000.0;
+        let position = requests.partition_point(|r| r.total_ms >= total_ms);
+        if position < capacity {
+            requests.insert(
+                position,
+                SlowRequest {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    total_ms,
+                    db_ms: db.as_secs_f64() * 1000.0,
+                },
+            );
+        }
+        requests.truncate(capacity);
+    }
+
+    // the kept requests, slowest first
+    pub fn snapshot(&self) -> Vec<SlowRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+// handler function which lists the slowest requests
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/admin/slowest", method = "GET"))]
+pub async fn list_slowest(
+    State(config): State<Arc<Config>>,
+    State(slowest): State<Arc<SlowestRequests>>,
+) -> impl IntoResponse {
+    let mut requests = slowest.snapshot();
+    requests.truncate(config.slowest_requests);
+    (StatusCode::OK, Json(requests))
+}
@@ -0,0 +1,62 @@
+// sql_log.rs
+// SQL statement logging for development, enabled by APP_ENV=development
+// every statement a connection runs is logged at debug level to the "sql" tracing target with
+// its bound parameters filled in, as SQLite expands them, in production the hook is never
+// installed so parameters (which may hold personal data) can't reach the logs whatever the
+// log level, SQLx's own statement logging is turned off in both cases as it can't show
+// parameters
+
+use futures::future::BoxFuture;
+use libsqlite3_sys::{
+    sqlite3_expanded_sql, sqlite3_free, sqlite3_stmt, sqlite3_trace_v2, SQLITE_TRACE_STMT,
+};
+use sqlx::sqlite::SqliteConnection;
+use std::ffi::{c_uint, c_void, CStr};
+use std::os::raw::c_int;
+use std::ptr;
+use tracing::debug;
+
+// install the statement trace on a new connection, used as the pool's after_connect hook
+pub fn trace_statements(conn: &mut SqliteConnection) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+    Box::pin(async move {
+        let mut handle = conn.lock_handle().await?;
+        // SAFETY: the handle is locked, so the connection's worker thread isn't using it, and
+        // the callback keeps no state, so there's nothing for the context pointer to outlive
+        let rc = unsafe {
+            sqlite3_trace_v2(
+                handle.as_raw_handle().as_ptr(),
+                SQLITE_TRACE_STMT as c_uint,
+                Some(log_statement),
+                ptr::null_mut(),
+            )
+        };
+        if rc != 0 {
+            return Err(sqlx::Error::Protocol(format!(
+                "could not install the SQL statement trace, SQLite error {}",
+                rc
+            )));
+        }
+        Ok(())
+    })
+}
+
+// called by SQLite as each statement starts, statement is the prepared statement being run
+unsafe extern "C" fn log_statement(
+    event: c_uint,
+    _context: *mut c_void,
+    statement: *mut c_void,
+    _sql: *mut c_void,
+) -> c_int {
+    if event != SQLITE_TRACE_STMT as c_uint {
+        return 0;
+    }
+
+    // SAFETY: for SQLITE_TRACE_STMT the third argument is the statement, the expanded text is
+    // allocated by SQLite, NUL terminated, and must be released with sqlite3_free
+    let expanded = sqlite3_expanded_sql(statement as *mut sqlite3_stmt);
+    if !expanded.is_null() {
+        debug!(target: "sql", "{}", CStr::from_ptr(expanded).to_string_lossy());
+        sqlite3_free(expanded as *mut c_void);
+    }
+    0
+}
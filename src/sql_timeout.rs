@@ -0,0 +1,35 @@
+// sql_timeout.rs
+// bounds the database-bound part of a request by SQL_TIMEOUT_MS, a query still running when
+// the time is up is cancelled by dropping its future and the request fails with a 504
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::server_timing;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+use tracing::warn;
+
+// run a query, or a transaction's worth of queries, under the configured timeout
+// the time spent is reported in the Server-Timing header when that's enabled
+pub async fn limit<T, E, Fut>(config: &Config, query: Fut) -> Result<T, AppError>
+where
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<AppError>,
+{
+    let start = Instant::now();
+    let result = if config.sql_timeout_ms == 0 {
+        query.await.map_err(Into::into)
+    } else {
+        let limit = Duration::from_millis(config.sql_timeout_ms);
+        match timeout(limit, query).await {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => {
+                warn!("database query cancelled after {:?}", limit);
+                Err(AppError::Timeout)
+            }
+        }
+    };
+    server_timing::record(server_timing::DB, start.elapsed());
+    result
+}
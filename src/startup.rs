@@ -0,0 +1,49 @@
+// startup.rs
+// the window between the server starting to listen and the schema being up to date
+// migrations run in the background so the server answers straight away, until they finish the
+// database routes are refused with a 503 and a Retry-After rather than failing on tables or
+// columns that don't exist yet, /health_check and the pages that don't touch the database are
+// served throughout and /readyz reports "starting"
+
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// seconds a client refused during startup is told to wait before trying again
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+// struct to hold whether the server is still starting, shared by every request
+#[derive(Clone, Debug)]
+pub struct StartingUp(Arc<AtomicBool>);
+
+impl StartingUp {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+
+    pub fn is_starting(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    // called once the migrations have run, database routes are served from then on
+    pub fn finish(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+// middleware function which refuses database routes until startup has finished
+pub async fn reject_while_starting(
+    State(starting_up): State<StartingUp>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if starting_up.is_starting() {
+        return Err(AppError::Starting);
+    }
+    Ok(next.run(request).await)
+}
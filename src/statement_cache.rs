@@ -0,0 +1,32 @@
+// statement_cache.rs
+// SQLx keeps a cache of prepared statements on every connection so the hot queries aren't
+// prepared again each time they run, its size is set with STATEMENT_CACHE_CAPACITY
+// each time a pooled connection is handed out its cache use is logged at debug level to the
+// "statement_cache" tracing target, a cache that is always full is evicting and may be too small
+
+use futures::future::BoxFuture;
+use sqlx::pool::PoolConnectionMetadata;
+use sqlx::sqlite::SqliteConnection;
+use sqlx::Connection;
+use tracing::debug;
+
+// report the cache of a connection about to be reused, used as the pool's before_acquire hook
+// the connection is always accepted
+pub fn log_reuse(
+    conn: &mut SqliteConnection,
+    meta: PoolConnectionMetadata,
+    capacity: usize,
+) -> BoxFuture<'_, Result<bool, sqlx::Error>> {
+    Box::pin(async move {
+        let cached = conn.cached_statements_size();
+        debug!(
+            target: "statement_cache",
+            cached,
+            capacity,
+            full = cached >= capacity,
+            idle_ms = meta.idle_for.as_millis() as u64,
+            "reusing connection"
+        );
+        Ok(true)
+    })
+}
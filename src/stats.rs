@@ -0,0 +1,68 @@
+// stats.rs
+// lightweight runtime statistics, a request counter and a background task which
+// periodically logs it alongside the database pool's size and idle connections
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::sqlite::SqlitePool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+// struct to hold counters shared between the middleware and the stats task
+#[derive(Debug, Default)]
+pub struct RequestStats {
+    served: AtomicU64,
+}
+
+impl RequestStats {
+    // total requests served since startup
+    pub fn served(&self) -> u64 {
+        self.served.load(Ordering::Relaxed)
+    }
+}
+
+// middleware function which counts every request once it has been handled
+pub async fn count_requests(
+    State(stats): State<Arc<RequestStats>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    stats.served.fetch_add(1, Ordering::Relaxed);
+    response
+}
+
+// log pool and request statistics every period until shutdown is signalled
+pub async fn log_stats(
+    pool: SqlitePool,
+    stats: Arc<RequestStats>,
+    period: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick completes immediately, skip it so the first report comes after one period
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                info!(
+                    pool_size = pool.size(),
+                    pool_idle = pool.num_idle(),
+                    requests_served = stats.served(),
+                    "server stats"
+                );
+            }
+        }
+    }
+    info!("stats task stopped");
+}
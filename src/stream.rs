@@ -0,0 +1,160 @@
+// stream.rs
+// streams every record as newline-delimited JSON (NDJSON) without buffering the whole table
+// rows are read on a separate task and handed to the response body through a bounded channel,
+// when the client disconnects the body is dropped, the channel closes and the task stops
+// fetching, which releases the SQL cursor and its pooled connection
+// a request with a Range header is answered from a snapshot of the same output instead, see
+// range.rs
+
+use crate::error::AppError;
+use crate::{range, TestRecord};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::stream::{poll_fn, StreamExt};
+use sqlx::sqlite::SqlitePool;
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, Instrument, Span};
+
+// most rows written into a single chunk, each chunk is flushed to the client as it's sent
+const ROWS_PER_CHUNK: usize = 64;
+
+// chunks buffered between the database task and the response body
+const CHUNK_BUFFER: usize = 4;
+
+// media type of the export
+pub const NDJSON: &str = "application/x-ndjson";
+
+// the query the export is read with, in a fixed order so a snapshot of it is repeatable
+pub const EXPORT_SQL: &str = "SELECT * FROM test ORDER BY id";
+
+// append a record to the export as one line of JSON
+pub fn write_line(buffer: &mut Vec<u8>, record: &TestRecord) {
+    serde_json::to_writer(&mut *buffer, record).expect("a record always serializes to JSON");
+    buffer.push(b'\n');
+}
+
+// handler function for the route which streams all records as NDJSON
+// the response has no Content-Length so it's sent with chunked transfer encoding
+#[axum_macros::debug_handler]
+#[instrument(skip_all, fields(route = "/database_stream", method = "GET"))]
+pub async fn stream_data(
+    State(pool): State<SqlitePool>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    if headers.contains_key(header::RANGE) {
+        return range::export(&pool, &headers).await;
+    }
+
+    let (tx, rx) = mpsc::channel(CHUNK_BUFFER);
+
+    tokio::spawn(send_rows(pool, tx).instrument(Span::current()));
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, NDJSON),
+            (header::ACCEPT_RANGES, "bytes"),
+        ],
+        body(rx),
+    )
+        .into_response())
+}
+
+// read every record and send it down the channel in chunks, until the rows run out or the
+// receiving body is dropped, answers with the number of records sent
+async fn send_rows(pool: SqlitePool, tx: mpsc::Sender<Result<Bytes, sqlx::Error>>) -> usize {
+    let mut rows = sqlx::query_as::<_, TestRecord>(EXPORT_SQL)
+        .fetch(&pool)
+        .ready_chunks(ROWS_PER_CHUNK);
+    let mut sent = 0;
+
+    while let Some(batch) = rows.next().await {
+        let mut chunk = Vec::new();
+        let mut failure = None;
+        for row in batch {
+            match row {
+                Ok(record) => {
+                    write_line(&mut chunk, &record);
+                    sent += 1;
+                }
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+
+        // send what was read before reporting an error, a failed send means
+        // the client has gone away so stop reading
+        if !chunk.is_empty() && tx.send(Ok(chunk.into())).await.is_err() {
+            info!(
+                "client disconnected after {} records, stopping stream",
+                sent
+            );
+            return sent;
+        }
+        if let Some(e) = failure {
+            // an error in the body aborts the response, so the client sees it fail
+            error!("database error while streaming records: {}", e);
+            let _ = tx.send(Err(e)).await;
+            return sent;
+        }
+    }
+    info!("streamed {} records", sent);
+    sent
+}
+
+// the response body, fed by the channel send_rows writes to
+fn body(mut rx: mpsc::Receiver<Result<Bytes, sqlx::Error>>) -> Body {
+    Body::from_stream(poll_fn(move |cx| rx.poll_recv(cx)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    const RECORDS: i32 = 10_000;
+
+    #[tokio::test]
+    async fn dropping_the_body_stops_the_fetch() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, 1..=RECORDS).await;
+
+        let (tx, rx) = mpsc::channel(CHUNK_BUFFER);
+        let task = tokio::spawn(send_rows(pool.clone(), tx));
+        let mut chunks = body(rx).into_data_stream();
+        let first = chunks
+            .next()
+            .await
+            .expect("a chunk")
+            .expect("a chunk reads");
+        assert!(first.ends_with(b"\n"));
+
+        // the cursor holds the only connection while the export is under way
+        assert!(pool.try_acquire().is_none());
+
+        drop(chunks);
+        let sent = timeout(Duration::from_secs(5), task)
+            .await
+            .expect("the fetch stops once the body is gone")
+            .expect("the task doesn't panic");
+        assert!(
+            sent < RECORDS as usize,
+            "only {} of {} records were read",
+            sent,
+            RECORDS
+        );
+        assert!(sent <= ROWS_PER_CHUNK * (CHUNK_BUFFER + 2));
+        timeout(Duration::from_secs(5), pool.acquire())
+            .await
+            .expect("the connection is released")
+            .expect("the connection is usable");
+    }
+}
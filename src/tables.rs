@@ -0,0 +1,563 @@
+// tables.rs
+// generic CRUD over any table in the registry below, through routes of the form
+// "/db/{table}/read", "/db/{table}/create" and "/db/{table}/{id}"
+// SQL is only ever built from the registry's own table and column names, the table in the path
+// just selects an entry, so it can't be used for injection, rows travel as JSON objects
+// changes to an audited table are written to the audit log like the dedicated routes do
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::{Json, Path, Query};
+use crate::{
+    audit, check_quota, compress, find_record, id_format, outbox, retry, server_timing,
+    sql_timeout, TestRecord,
+};
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::query::Query as SqlQuery;
+use sqlx::sqlite::{SqliteArguments, SqliteConnection, SqlitePool, SqliteRow};
+use sqlx::{Row, Sqlite};
+use std::sync::Arc;
+use tracing::{info, instrument};
+
+// the type of a column, which decides how it's read, bound and validated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnKind {
+    Integer,
+    Text,
+    // text that must be a YYYY-MM-DD date
+    Date,
+}
+
+// struct to hold a column of a registered table
+#[derive(Debug)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: ColumnKind,
+    // the test table's message, which may be held zstd compressed, see compress.rs
+    // values written here are always stored as plain text
+    pub compressed: bool,
+}
+
+// struct to hold a registered table, the first column is its integer id
+#[derive(Debug)]
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [Column],
+    // changes are written to the audit log, only the test table has one
+    pub audited: bool,
+}
+
+// tables reachable through the generic routes
+pub const TABLES: &[Table] = &[
+    Table {
+        name: "test",
+        columns: &[
+            Column {
+                name: "id",
+                kind: ColumnKind::Integer,
+                compressed: false,
+            },
+            Column {
+                name: "date",
+                kind: ColumnKind::Date,
+                compressed: false,
+            },
+            Column {
+                name: "message",
+                kind: ColumnKind::Text,
+                compressed: true,
+            },
+        ],
+        audited: true,
+    },
+    Table {
+        name: "notes",
+        columns: &[
+            Column {
+                name: "id",
+                kind: ColumnKind::Integer,
+                compressed: false,
+            },
+            Column {
+                name: "title",
+                kind: ColumnKind::Text,
+                compressed: false,
+            },
+            Column {
+                name: "body",
+                kind: ColumnKind::Text,
+                compressed: false,
+            },
+        ],
+        audited: false,
+    },
+];
+
+impl Table {
+    // look a table up by the name used in the path
+    fn find(name: &str) -> Result<&'static Table, AppError> {
+        TABLES
+            .iter()
+            .find(|table| table.name == name)
+            .ok_or_else(|| AppError::NotFound(format!("no table named {:?}", name)))
+    }
+
+    fn id_column(&self) -> &'static str {
+        self.columns[0].name
+    }
+
+    // every column except the id
+    fn fields(&self) -> &'static [Column] {
+        &self.columns[1..]
+    }
+
+    // the columns read back from the table, with the compressed copy of a compressed column
+    fn column_list(&self) -> String {
+        self.columns
+            .iter()
+            .flat_map(|column| {
+                let compressed = column.compressed.then_some(compress::COLUMN);
+                std::iter::once(column.name).chain(compressed)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // turn a row into a JSON object, ids are written as ID_AS_STRING says
+    fn to_json(&self, row: &SqliteRow) -> Result<Value, sqlx::Error> {
+        let mut object = Map::new();
+        for (index, column) in self.columns.iter().enumerate() {
+            let value = match column.kind {
+                ColumnKind::Integer if index == 0 => {
+                    json!(id_format::Id(row.try_get::<i32, _>(column.name)?))
+                }
+                ColumnKind::Integer => json!(row.try_get::<Option<i64>, _>(column.name)?),
+                ColumnKind::Text if column.compressed => json!(compress::load(row)?),
+                ColumnKind::Text | ColumnKind::Date => {
+                    json!(row.try_get::<Option<String>, _>(column.name)?)
+                }
+            };
+            object.insert(column.name.to_string(), value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    // check a request body against the table's fields, every field is needed unless partial
+    // is set, the id and unknown fields are rejected, the checked fields are returned in order
+    fn validate(
+        &self,
+        body: &Map<String, Value>,
+        partial: bool,
+    ) -> Result<Vec<(&'static Column, Value)>, AppError> {
+        if let Some(unknown) = body
+            .keys()
+            .find(|key| !self.fields().iter().any(|column| column.name == *key))
+        {
+            return Err(AppError::BadRequest(format!(
+                "{:?} is not a field of table {} that can be set",
+                unknown, self.name
+            )));
+        }
+
+        let mut values = Vec::new();
+        for column in self.fields() {
+            let value = match body.get(column.name) {
+                None if partial => continue,
+                None | Some(Value::Null) => {
+                    return Err(AppError::BadRequest(format!("{} is required", column.name)))
+                }
+                Some(value) => value,
+            };
+            let valid = match column.kind {
+                ColumnKind::Integer => value.is_i64(),
+                ColumnKind::Text => value.as_str().is_some_and(|s| !s.trim().is_empty()),
+                ColumnKind::Date => value
+                    .as_str()
+                    .is_some_and(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok()),
+            };
+            if !valid {
+                return Err(AppError::BadRequest(match column.kind {
+                    ColumnKind::Integer => format!("{} must be an integer", column.name),
+                    ColumnKind::Text => format!("{} must be a non-empty string", column.name),
+                    ColumnKind::Date => format!("{} must be a YYYY-MM-DD date", column.name),
+                }));
+            }
+            values.push((column, value.clone()));
+        }
+        if values.is_empty() {
+            return Err(AppError::BadRequest("no fields to change".to_string()));
+        }
+        Ok(values)
+    }
+
+    // fetch one row by id on a connection, used inside the write transactions, 404 if it's missing
+    async fn find_row(&self, conn: &mut SqliteConnection, id: i32) -> Result<SqliteRow, AppError> {
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {} = $1",
+            self.column_list(),
+            self.name,
+            self.id_column()
+        );
+        sqlx::query(&sql)
+            .bind(id)
+            .fetch_optional(conn)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("no row in {} with id {}", self.name, id)))
+    }
+
+    // the record as it is before a change, read in full as the dedicated routes read it since
+    // the audit log keeps every column, None for a table that isn't audited
+    async fn before_image(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i32,
+    ) -> Result<Option<TestRecord>, sqlx::Error> {
+        if !self.audited {
+            return Ok(None);
+        }
+        find_record(conn, id).await
+    }
+
+    // write an audit entry for a change to an audited table, with the record as it was before
+    async fn audit(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i32,
+        operation: &str,
+        before: Option<&TestRecord>,
+    ) -> Result<(), sqlx::Error> {
+        if !self.audited {
+            return Ok(());
+        }
+        audit::record_change(conn, id, operation, before).await
+    }
+}
+
+// bind a validated JSON value as its column's type
+fn bind_value<'q>(
+    query: SqlQuery<'q, Sqlite, SqliteArguments<'q>>,
+    column: &Column,
+    value: &Value,
+) -> SqlQuery<'q, Sqlite, SqliteArguments<'q>> {
+    match column.kind {
+        ColumnKind::Integer => query.bind(value.as_i64()),
+        ColumnKind::Text | ColumnKind::Date => query.bind(value.as_str().map(str::to_string)),
+    }
+}
+
+// struct to hold the paging parameters of the generic read route
+#[derive(Deserialize, Debug)]
+pub struct PageParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+// handler function for the route which reads a page of rows from a table, in id order
+// limit falls back to the configured default page size and is capped at the maximum
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/db/{table}/read", method = "GET", table = %table))]
+pub async fn read_rows(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(table): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let table = Table::find(&table)?;
+    let limit = params.limit.unwrap_or(config.default_page_size);
+    let offset = params.offset.unwrap_or(0);
+    if limit < 1 {
+        return Err(AppError::BadRequest(
+            "limit must be a positive integer".to_string(),
+        ));
+    }
+    if offset < 0 {
+        return Err(AppError::BadRequest(
+            "offset must not be negative".to_string(),
+        ));
+    }
+
+    let sql = format!(
+        "SELECT {} FROM {} ORDER BY {} LIMIT $1 OFFSET $2",
+        table.column_list(),
+        table.name,
+        table.id_column()
+    );
+    let query = sqlx::query(&sql)
+        .bind(limit.min(config.max_page_size))
+        .bind(offset)
+        .fetch_all(&pool);
+    let rows = sql_timeout::limit(&config, query)
+        .await?
+        .iter()
+        .map(|row| table.to_json(row))
+        .collect::<Result<Vec<_>, _>>()?;
+    info!("read {} rows", rows.len());
+
+    Ok((StatusCode::OK, server_timing::json(rows)))
+}
+
+// handler function for the route which returns a single row by id
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/db/{table}/{id}", method = "GET", table = %table, id = id))]
+pub async fn get_row(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path((table, id)): Path<(String, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let table = Table::find(&table)?;
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {} = $1",
+        table.column_list(),
+        table.name,
+        table.id_column()
+    );
+    let query = sqlx::query(&sql).bind(id).fetch_optional(&pool);
+    let row = sql_timeout::limit(&config, query)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("no row in {} with id {}", table.name, id)))?;
+    info!("row found");
+
+    Ok((StatusCode::OK, server_timing::json(table.to_json(&row)?)))
+}
+
+// handler function for the route which adds a row, every field but the id is required
+// the id is assigned by the database, the stored row is returned
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/db/{table}/create", method = "POST", table = %table))]
+pub async fn create_row(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path(table): Path<String>,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<impl IntoResponse, AppError> {
+    let table = Table::find(&table)?;
+    let values = table.validate(&body, false)?;
+    let names: Vec<_> = values.iter().map(|(column, _)| column.name).collect();
+    let placeholders: Vec<_> = (1..=values.len()).map(|n| format!("${}", n)).collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({}) RETURNING {}",
+        table.name,
+        names.join(", "),
+        placeholders.join(", "),
+        table.column_list()
+    );
+
+    let (pool, config, sql, values) = (&pool, &config, &sql, &values);
+    let row = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        if table.name == "test" {
+            check_quota(&mut tx, config).await?;
+        }
+        let mut query = sqlx::query(sql);
+        for (column, value) in values {
+            query = bind_value(query, column, value);
+        }
+        let row = query.fetch_one(&mut *tx).await?;
+        let id = row.try_get(table.id_column())?;
+        table.audit(&mut tx, id, audit::CREATE, None).await?;
+        // a record created here is announced like one from /database_create
+        if table.name == "test" {
+            let record = find_record(&mut tx, id)
+                .await?
+                .ok_or(sqlx::Error::RowNotFound)?;
+            outbox::enqueue(&mut tx, config, outbox::RECORD_CREATED, &record).await?;
+        }
+        tx.commit().await?;
+        Ok(table.to_json(&row)?)
+    })
+    .await?;
+    info!("row created");
+
+    Ok((StatusCode::CREATED, server_timing::json(row)))
+}
+
+// handler function for the route which changes some fields of a row, the updated row is returned
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/db/{table}/{id}", method = "PUT", table = %table, id = id))]
+pub async fn update_row(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path((table, id)): Path<(String, i32)>,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<impl IntoResponse, AppError> {
+    let table = Table::find(&table)?;
+    let values = table.validate(&body, true)?;
+    let assignments: Vec<_> = values
+        .iter()
+        .enumerate()
+        .map(|(n, (column, _))| format!("{} = ${}", column.name, n + 1))
+        .collect();
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} = ${} RETURNING {}",
+        table.name,
+        assignments.join(", "),
+        table.id_column(),
+        values.len() + 1,
+        table.column_list()
+    );
+
+    let (pool, sql, values) = (&pool, &sql, &values);
+    let row = retry::on_busy(&config, || async move {
+        let mut tx = pool.begin().await?;
+        table.find_row(&mut tx, id).await?;
+        let before = table.before_image(&mut tx, id).await?;
+        let mut query = sqlx::query(sql);
+        for (column, value) in values {
+            query = bind_value(query, column, value);
+        }
+        let row = query.bind(id).fetch_one(&mut *tx).await?;
+        table
+            .audit(&mut tx, id, audit::UPDATE, before.as_ref())
+            .await?;
+        tx.commit().await?;
+        Ok(table.to_json(&row)?)
+    })
+    .await?;
+    info!("row updated");
+
+    Ok((StatusCode::OK, server_timing::json(row)))
+}
+
+// handler function for the route which removes a row, the removed row is returned
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/db/{table}/{id}", method = "DELETE", table = %table, id = id))]
+pub async fn delete_row(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Path((table, id)): Path<(String, i32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let table = Table::find(&table)?;
+    let sql = format!(
+        "DELETE FROM {} WHERE {} = $1",
+        table.name,
+        table.id_column()
+    );
+
+    let (pool, sql) = (&pool, &sql);
+    let row = retry::on_busy(&config, || async move {
+        let mut tx = pool.begin().await?;
+        let row = table.find_row(&mut tx, id).await?;
+        let before = table.before_image(&mut tx, id).await?;
+        sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+        table
+            .audit(&mut tx, id, audit::DELETE, before.as_ref())
+            .await?;
+        tx.commit().await?;
+        Ok(table.to_json(&row)?)
+    })
+    .await?;
+    info!("row deleted");
+
+    Ok((StatusCode::OK, server_timing::json(row)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use axum::body::to_bytes;
+    use axum::response::Response;
+
+    fn config() -> Arc<Config> {
+        Arc::new(Config::from_env().expect("the default configuration"))
+    }
+
+    async fn body(response: Response) -> Value {
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("the body reads");
+        serde_json::from_slice(&body).expect("the body is JSON")
+    }
+
+    // the operation and before-image of the latest audit entry for a record
+    async fn latest_audit(pool: &SqlitePool, id: i32) -> (String, TestRecord) {
+        let mut conn = pool.acquire().await.expect("a connection");
+        let entry = audit::latest_change(&mut conn, id)
+            .await
+            .expect("the audit log reads")
+            .expect("the change was audited");
+        let before = entry.before_image.expect("a before-image").0;
+        (entry.operation, before)
+    }
+
+    #[tokio::test]
+    async fn create_through_the_generic_route_queues_its_event() {
+        let pool = test_support::memory_pool().await;
+        let mut config = Config::from_env().expect("the default configuration");
+        config.webhook_url = Some("http://127.0.0.1:9/hook".to_string());
+
+        let record = json!({ "date": "2026-01-01", "message": "created" });
+        let response = create_row(
+            State(pool.clone()),
+            State(Arc::new(config)),
+            Path("test".to_string()),
+            Json(record.as_object().expect("an object").clone()),
+        )
+        .await
+        .expect("the create succeeds")
+        .into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("the body reads");
+        let created: Value = serde_json::from_slice(&body).expect("the body is JSON");
+
+        let (event, payload): (String, String) =
+            sqlx::query_as("SELECT event, payload FROM outbox")
+                .fetch_one(&pool)
+                .await
+                .expect("one event is queued");
+        assert_eq!(event, outbox::RECORD_CREATED);
+        let payload: Value = serde_json::from_str(&payload).expect("the payload is JSON");
+        assert_eq!(payload["record"]["id"], created["id"]);
+        assert_eq!(payload["record"]["message"], "created");
+    }
+
+    #[tokio::test]
+    async fn update_through_the_generic_route_is_audited() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, [1]).await;
+
+        let change = json!({ "message": "changed" });
+        let response = update_row(
+            State(pool.clone()),
+            State(config()),
+            Path(("test".to_string(), 1)),
+            Json(change.as_object().expect("an object").clone()),
+        )
+        .await
+        .expect("the update succeeds")
+        .into_response();
+        assert_eq!(body(response).await["message"], "changed");
+
+        let (operation, before) = latest_audit(&pool, 1).await;
+        assert_eq!(operation, audit::UPDATE);
+        assert_eq!(before.message, "message 1");
+        assert!(before.created_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn delete_through_the_generic_route_is_audited() {
+        let pool = test_support::memory_pool().await;
+        test_support::insert(&pool, [1]).await;
+
+        let response = delete_row(
+            State(pool.clone()),
+            State(config()),
+            Path(("test".to_string(), 1)),
+        )
+        .await
+        .expect("the delete succeeds")
+        .into_response();
+        assert_eq!(body(response).await["message"], "message 1");
+
+        let (operation, before) = latest_audit(&pool, 1).await;
+        assert_eq!(operation, audit::DELETE);
+        assert_eq!(before.message, "message 1");
+        assert!(before.created_at.is_some());
+    }
+}
@@ -0,0 +1,39 @@
+// test_support.rs
+// a database for the unit tests, in memory and migrated with the server's own migrations
+
+use crate::migrations;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::str::FromStr;
+
+// a migrated in-memory database, which belongs to its connection, so the pool keeps exactly one
+// open for as long as it lives
+pub async fn memory_pool() -> SqlitePool {
+    let options = SqliteConnectOptions::from_str("sqlite::memory:").expect("a valid database URL");
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .min_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .expect("an in-memory database opens");
+    migrations::run(&pool).await.expect("the migrations apply");
+    pool
+}
+
+// insert a record for each id, in the order given, with a message naming its id
+pub async fn insert(pool: &SqlitePool, ids: impl IntoIterator<Item = i32>) {
+    let mut tx = pool.begin().await.expect("a transaction starts");
+    for id in ids {
+        sqlx::query(
+            "INSERT INTO test (id, date, message, updated_at) \
+             VALUES ($1, '2023-01-25', $2, '2026-01-01T00:00:00.000Z')",
+        )
+        .bind(id)
+        .bind(format!("message {}", id))
+        .execute(&mut *tx)
+        .await
+        .expect("a record inserts");
+    }
+    tx.commit().await.expect("the records commit");
+}
@@ -0,0 +1,34 @@
+// timeout.rs
+// per-route request timeouts, each router group gets its own limit so simple CRUD routes are cut
+// off quickly while bulk routes get longer, a request that runs out is answered with a 503
+// the limit covers producing the response, a streamed body keeps flowing once it has started
+
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Duration;
+use tracing::warn;
+
+// middleware function which runs the rest of the stack under a limit of the given seconds
+// a limit of 0 lets requests run for as long as they need
+pub async fn limit(
+    State(secs): State<u64>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if secs == 0 {
+        return Ok(next.run(request).await);
+    }
+
+    let path = request.uri().path().to_string();
+    match tokio::time::timeout(Duration::from_secs(secs), next.run(request)).await {
+        Ok(response) => Ok(response),
+        Err(_) => {
+            warn!("request to {} took longer than {} seconds", path, secs);
+            Err(AppError::RequestTimeout(secs))
+        }
+    }
+}
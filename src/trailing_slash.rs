@@ -0,0 +1,55 @@
+// trailing_slash.rs
+// axum routes "/database_read/" separately from "/database_read", so a client adding a slash
+// got a 404, with TRAILING_SLASH=strip (the default) the slash is removed before the request is
+// routed, with redirect the client is sent to the path without it with a 308, off keeps the 404
+// this wraps the whole router from the outside, a layer on the router would only run after
+// the route was already chosen
+
+use crate::config::{Config, TrailingSlash};
+use axum::{
+    extract::{Request, State},
+    http::{header, uri::PathAndQuery, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tracing::debug;
+
+// middleware function which strips or redirects away trailing slashes
+pub async fn normalize(
+    State(config): State<Arc<Config>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let trimmed = path.trim_end_matches('/');
+    if config.trailing_slash == TrailingSlash::Off || path == "/" || trimmed == path {
+        return next.run(request).await;
+    }
+
+    // the root is the one path that is only a slash
+    let trimmed = if trimmed.is_empty() { "/" } else { trimmed };
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed.to_string(),
+    };
+
+    match config.trailing_slash {
+        TrailingSlash::Redirect => {
+            debug!("redirecting {} to {}", path, trimmed);
+            (
+                StatusCode::PERMANENT_REDIRECT,
+                [(header::LOCATION, path_and_query)],
+            )
+                .into_response()
+        }
+        _ => {
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+            if let Ok(uri) = Uri::from_parts(parts) {
+                *request.uri_mut() = uri;
+            }
+            next.run(request).await
+        }
+    }
+}
@@ -0,0 +1,32 @@
+// uri_limit.rs
+// rejects requests whose URI is longer than MAX_URI_LEN with a 414, a cheap early defence
+// against oversized query strings, checked before any other middleware or handler runs
+
+use crate::config::Config;
+use crate::error::AppError;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+use tracing::warn;
+
+// middleware function which checks the length of the path and query
+// a MAX_URI_LEN of 0 lets every request through
+pub async fn check_uri_len(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let len = request.uri().to_string().len();
+    if config.max_uri_len > 0 && len > config.max_uri_len {
+        warn!(
+            "rejected request with a {} byte URI, the maximum is {}",
+            len, config.max_uri_len
+        );
+        return Err(AppError::UriTooLong(config.max_uri_len));
+    }
+
+    Ok(next.run(request).await)
+}
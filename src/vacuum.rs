@@ -0,0 +1,124 @@
+// vacuum.rs
+// compaction of the SQLite file, on demand through POST /admin/vacuum and optionally every
+// VACUUM_INTERVAL_SECS
+// a full VACUUM rebuilds the whole file and holds an exclusive lock while it runs, so writes
+// (and, once it commits, reads) wait for it and it needs free disk space of up to the size of
+// the database, it isn't bounded by SQL_TIMEOUT_MS, an incremental vacuum only releases free
+// pages and is cheap, but does nothing unless the database uses auto_vacuum = INCREMENTAL
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Query;
+use crate::flag;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json},
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, instrument, warn};
+
+// struct to hold the options of the vacuum route
+#[derive(Deserialize, Debug)]
+pub struct VacuumParams {
+    incremental: Option<String>,
+}
+
+// handler function for the route which compacts the database file and reports its size
+// before and after, ?incremental=true runs an incremental vacuum instead of a full one
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/admin/vacuum", method = "POST"))]
+pub async fn vacuum_database(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<VacuumParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let incremental = flag::parse("incremental", params.incremental.as_deref())?;
+    let file = config.database_file();
+    let before = file_size(file).await;
+    vacuum(&pool, incremental).await?;
+    let after = file_size(file).await;
+    info!(
+        "vacuum finished, database file {:?} bytes before, {:?} after",
+        before, after
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({
+            "mode": if incremental { "incremental" } else { "full" },
+            "before_bytes": before,
+            "after_bytes": after,
+            "reclaimed_bytes": before.zip(after).map(|(before, after)| before.saturating_sub(after)),
+        })),
+    ))
+}
+
+// run VACUUM (or an incremental vacuum) then checkpoint the write-ahead log, so the main
+// file reflects the result
+async fn vacuum(pool: &SqlitePool, incremental: bool) -> Result<(), sqlx::Error> {
+    let statement = if incremental {
+        "PRAGMA incremental_vacuum"
+    } else {
+        "VACUUM"
+    };
+    sqlx::query(statement).execute(pool).await?;
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+// size of the database in bytes, the main file plus its write-ahead log, so pages waiting for
+// a checkpoint are counted either way, None when the main file can't be read
+async fn file_size(file: &str) -> Option<u64> {
+    let main = match tokio::fs::metadata(file).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) => {
+            warn!("could not read the size of {}: {}", file, e);
+            return None;
+        }
+    };
+    // the log only exists while the database is in WAL mode
+    let wal = tokio::fs::metadata(format!("{}-wal", file))
+        .await
+        .map_or(0, |metadata| metadata.len());
+    Some(main + wal)
+}
+
+// run a full vacuum every period until shutdown is signalled
+pub async fn vacuum_periodically(
+    pool: SqlitePool,
+    file: String,
+    period: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick completes immediately, skip it so the first vacuum comes after one period
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = ticker.tick() => {
+                let before = file_size(&file).await;
+                match vacuum(&pool, false).await {
+                    Ok(()) => info!(
+                        "scheduled vacuum finished, database file {:?} bytes before, {:?} after",
+                        before,
+                        file_size(&file).await
+                    ),
+                    Err(e) => error!("scheduled vacuum failed: {}", e),
+                }
+            }
+        }
+    }
+    info!("vacuum task stopped");
+}
@@ -0,0 +1,181 @@
+// view.rs
+// "/database_view", the records as an HTML table for reading in a browser without JavaScript,
+// paged with limit and offset like /database_read and linked to the previous and next pages
+// the page is rendered from templates/view.html by filling in its {{placeholders}}, every value
+// taken from the database is HTML escaped, when the records can't be read the page still renders
+// with a notice in place of the table and a 503
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::extract::Query;
+use crate::filter::Filter;
+use crate::{decode, queries, sql_timeout};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::sync::Arc;
+use tracing::{info, instrument, warn};
+
+// the page, embedded in the binary
+const VIEW_PAGE: &str = include_str!("../templates/view.html");
+
+// struct to hold the paging parameters of the view, the same as /database_read's
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ViewParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    // conditions such as "message~hello;id>5", see filter.rs
+    filter: Option<String>,
+}
+
+// handler function for the route which renders a page of records as an HTML table
+#[axum_macros::debug_handler(state = crate::AppState)]
+#[instrument(skip_all, fields(route = "/database_view", method = "GET"))]
+pub async fn view_records(
+    State(pool): State<SqlitePool>,
+    State(config): State<Arc<Config>>,
+    Query(params): Query<ViewParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(config.default_page_size);
+    let offset = params.offset.unwrap_or(0);
+    if limit < 1 {
+        return Err(AppError::BadRequest(
+            "limit must be a positive integer".to_string(),
+        ));
+    }
+    if offset < 0 {
+        return Err(AppError::BadRequest(
+            "offset must not be negative".to_string(),
+        ));
+    }
+    let limit = limit.min(config.max_page_size);
+    let filter = Filter::parse(params.filter.as_deref())?;
+
+    let page = match read_page(&pool, &config, &filter, limit, offset).await {
+        Ok((total, rows)) => {
+            info!("viewed {} records", rows.len());
+            let summary = match rows.len() {
+                0 => format!("No records to show, {} in total.", total),
+                shown => format!(
+                    "Records {} to {} of {}.",
+                    offset + 1,
+                    offset + shown as i64,
+                    total
+                ),
+            };
+            let link = |offset: i64, label: &str| {
+                let mut query = vec![("limit", limit.to_string()), ("offset", offset.to_string())];
+                if let Some(filter) = &params.filter {
+                    query.push(("filter", filter.clone()));
+                }
+                let query = serde_urlencoded::to_string(query).unwrap_or_default();
+                format!(
+                    "<a href=\"/database_view?{}\">{}</a>",
+                    escape(&query),
+                    label
+                )
+            };
+            let prev = if offset > 0 {
+                link((offset - limit).max(0), "Previous")
+            } else {
+                String::new()
+            };
+            let next = if offset + limit < total {
+                link(offset + limit, "Next")
+            } else {
+                String::new()
+            };
+            (
+                StatusCode::OK,
+                render(&summary, &rows.concat(), &prev, &next),
+            )
+        }
+        Err(e) => {
+            warn!("could not read records for the view: {:?}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                render(
+                    "Records can't be shown right now, try again shortly.",
+                    "",
+                    "",
+                    "",
+                ),
+            )
+        }
+    };
+
+    Ok(page)
+}
+
+// count the matching records and read a page of them as table rows
+async fn read_page(
+    pool: &SqlitePool,
+    config: &Config,
+    filter: &Filter,
+    limit: i64,
+    offset: i64,
+) -> Result<(i64, Vec<String>), AppError> {
+    let where_clause = filter.where_clause();
+    let next = filter.placeholders() + 1;
+
+    let sql = queries::count(&where_clause);
+    let query = filter.bind(sqlx::query(&sql)).fetch_one(pool);
+    let total: i64 = sql_timeout::limit(config, query).await?.try_get(0)?;
+
+    let sql = queries::page(&where_clause, next);
+    let query = filter
+        .bind(sqlx::query(&sql))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool);
+    let rows = sql_timeout::limit(config, query).await?;
+
+    // a message that isn't UTF-8 is shown base64 encoded, as ?raw=true would return it
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let record = serde_json::to_value(decode::record(row, true)?)
+                .expect("a record always serializes to JSON");
+            let field = |name: &str| match &record[name] {
+                Value::String(value) => escape(value),
+                value => escape(&value.to_string()),
+            };
+            Ok(format!(
+                "    <tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                field("id"),
+                field("date"),
+                field("message")
+            ))
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    Ok((total, rows))
+}
+
+// fill in the page template, the rows go in last so text in a record that looks like a
+// placeholder is left as it is
+fn render(summary: &str, rows: &str, prev: &str, next: &str) -> Html<String> {
+    Html(
+        VIEW_PAGE
+            .replace("{{summary}}", summary)
+            .replace("{{prev}}", prev)
+            .replace("{{next}}", next)
+            .replace("{{rows}}", rows),
+    )
+}
+
+// escape text for use in HTML content and attribute values
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -0,0 +1,173 @@
+// write_queue.rs
+// deferred inserts for write-heavy bursts, enabled with ASYNC_WRITES
+// create requests push the record onto a bounded in-memory queue and are answered with a 202
+// straight away, a background worker drains the queue into batched transactions, a full queue
+// sheds new creates with a 503, on shutdown the queue stays open until the server has answered
+// its in-flight requests, then it's closed and flushed within SHUTDOWN_DRAIN_TIMEOUT_SECS so
+// nothing that was accepted is lost, records are stored later than the response, so a read
+// straight after a 202 may not see them yet, and a record that fails to insert, or that finds
+// the table holding MAX_RECORDS by the time it's stored, is dropped and logged
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::{check_quota, insert_record, retry, TestRecord};
+use sqlx::sqlite::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+// most queued records stored by one transaction
+const BATCH_SIZE: usize = 100;
+
+// struct to hold the sending side of the queue, None when ASYNC_WRITES is off
+#[derive(Clone, Debug)]
+pub struct WriteQueue(Option<Sender<TestRecord>>);
+
+impl WriteQueue {
+    // a queue with room for capacity records, and the receiver the worker drains
+    pub fn new(capacity: usize) -> (Self, Receiver<TestRecord>) {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        (Self(Some(sender)), receiver)
+    }
+
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    // queue a record for the worker, a full or closed queue is reported as overloaded
+    pub fn push(&self, record: TestRecord) -> Result<(), AppError> {
+        let Some(sender) = &self.0 else {
+            return Err(AppError::Overloaded);
+        };
+        sender.try_send(record).map_err(|e| {
+            match e {
+                TrySendError::Full(_) => warn!("write queue is full, shedding create request"),
+                TrySendError::Closed(_) => warn!("write queue is closed, shedding create request"),
+            }
+            AppError::Overloaded
+        })
+    }
+}
+
+// store queued records until the server has stopped, then close the queue and flush what's
+// left, giving up on records still queued after SHUTDOWN_DRAIN_TIMEOUT_SECS
+pub async fn drain_writes(
+    pool: SqlitePool,
+    config: Arc<Config>,
+    mut receiver: Receiver<TestRecord>,
+    server_stopped: CancellationToken,
+) {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        tokio::select! {
+            _ = server_stopped.cancelled() => break,
+            received = receiver.recv_many(&mut batch, BATCH_SIZE) => {
+                if received == 0 {
+                    break;
+                }
+                store_batch(&pool, &config, &mut batch, &mut Outcome::default()).await;
+            }
+        }
+    }
+
+    // nothing can be queued any more, store everything that was, the outcome is counted outside
+    // the timed future so the records stored before a timeout are still reported
+    receiver.close();
+    let queued = batch.len() + receiver.len();
+    info!("flushing {} queued records before exit", queued);
+    let mut outcome = Outcome::default();
+    let flush = async {
+        while receiver.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+            store_batch(&pool, &config, &mut batch, &mut outcome).await;
+        }
+    };
+    let drain_timeout = Duration::from_secs(config.shutdown_drain_timeout_secs);
+    if timeout(drain_timeout, flush).await.is_err() {
+        // a batch cut off mid-transaction is rolled back, so it's lost along with the rest
+        error!(
+            "write queue flush timed out after {:?}, of {} queued records {} were stored, {} dropped and {} not stored",
+            drain_timeout,
+            queued,
+            outcome.stored,
+            outcome.dropped,
+            queued.saturating_sub(outcome.stored + outcome.dropped)
+        );
+        return;
+    }
+    info!(
+        "write queue stopped, {} queued records flushed, {} dropped",
+        outcome.stored, outcome.dropped
+    );
+}
+
+// struct to hold how many queued records were stored and how many were given up on
+#[derive(Debug, Default)]
+struct Outcome {
+    stored: usize,
+    dropped: usize,
+}
+
+// store a batch in one transaction, when that fails each record is retried on its own so one
+// bad record doesn't cost the rest of the batch, the batch is left empty, each commit is added
+// to the outcome as it happens
+async fn store_batch(
+    pool: &SqlitePool,
+    config: &Config,
+    batch: &mut Vec<TestRecord>,
+    outcome: &mut Outcome,
+) {
+    let records = &*batch;
+    let stored = retry::on_busy(config, || async move {
+        let mut tx = pool.begin().await?;
+        for record in records {
+            check_quota(&mut tx, config).await?;
+            insert_record(&mut tx, config, record).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    })
+    .await;
+
+    match stored {
+        Ok(()) => {
+            info!("stored {} queued records", records.len());
+            outcome.stored += records.len();
+        }
+        Err(e) if records.len() == 1 => {
+            error!("dropped queued record {}: {:?}", records[0].id, e);
+            outcome.dropped += 1;
+        }
+        Err(e) => {
+            warn!(
+                "could not store a batch of {} queued records, storing them one by one: {:?}",
+                records.len(),
+                e
+            );
+            for record in records {
+                let stored = retry::on_busy(config, || async move {
+                    let mut tx = pool.begin().await?;
+                    check_quota(&mut tx, config).await?;
+                    insert_record(&mut tx, config, record).await?;
+                    tx.commit().await?;
+                    Ok(())
+                })
+                .await;
+                match stored {
+                    Ok(()) => outcome.stored += 1,
+                    Err(e) => {
+                        error!("dropped queued record {}: {:?}", record.id, e);
+                        outcome.dropped += 1;
+                    }
+                }
+            }
+        }
+    }
+    batch.clear();
+}
@@ -0,0 +1,112 @@
+// xml.rs
+// XML output for older clients that can't take JSON, ?format=xml on /database_read and
+// /database_search, JSON stays the default
+// the schema is kept simple, a read is a <records> element holding a <record> per record and a
+// search a single <record>, each field of the record's JSON form becomes a child element of the
+// same name, e.g. <record><id>1</id><date>2023-01-25</date><message>hi</message></record>
+// the elements are written straight from the record's fields, quick-xml's serde support would
+// derive them from Serialize but the crate isn't available to this build
+
+use crate::decode::ReadRecord;
+use crate::error::AppError;
+use crate::{server_timing, TestRecord};
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::time::Instant;
+
+// media type of the XML responses
+const APPLICATION_XML: &str = "application/xml";
+
+// the format a read is answered in, from ?format=
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Xml,
+}
+
+impl Format {
+    // the format asked for, JSON when none is given and a 400 for anything unknown
+    pub fn parse(value: Option<&str>) -> Result<Self, AppError> {
+        match value {
+            None | Some("json") => Ok(Format::Json),
+            Some("xml") => Ok(Format::Xml),
+            Some(format) => Err(AppError::BadRequest(format!(
+                "invalid value for format: {:?}, expected json or xml",
+                format
+            ))),
+        }
+    }
+}
+
+// an XML response holding every record in a <records> element, timed like a JSON one
+pub fn records(records: &[ReadRecord]) -> Response {
+    let start = Instant::now();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<records>");
+    for record in records {
+        write_record(&mut xml, record.record(), record.message_encoding());
+    }
+    xml.push_str("</records>\n");
+    server_timing::record(server_timing::SERIALIZE, start.elapsed());
+    response(xml)
+}
+
+// an XML response holding a single record as its <record> element
+pub fn record(record: &TestRecord) -> Response {
+    let start = Instant::now();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    write_record(&mut xml, record, None);
+    xml.push('\n');
+    server_timing::record(server_timing::SERIALIZE, start.elapsed());
+    response(xml)
+}
+
+fn response(xml: String) -> Response {
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, APPLICATION_XML)],
+        xml,
+    )
+        .into_response()
+}
+
+// append a <record> with an element per field, in the order of the JSON form, fields the JSON
+// form leaves out are left out here too
+fn write_record(xml: &mut String, record: &TestRecord, message_encoding: Option<&str>) {
+    xml.push_str("<record>");
+    write_field(xml, "id", &record.id.to_string());
+    write_field(xml, "date", &record.date.to_string());
+    write_field(xml, "message", &record.message);
+    if let Some(updated_at) = &record.updated_at {
+        write_field(xml, "updated_at", updated_at);
+    }
+    if let Some(created_at) = &record.created_at {
+        write_field(xml, "created_at", created_at);
+    }
+    if let Some(message_encoding) = message_encoding {
+        write_field(xml, "message_encoding", message_encoding);
+    }
+    xml.push_str("</record>");
+}
+
+fn write_field(xml: &mut String, name: &str, text: &str) {
+    xml.push_str(&format!("<{0}>{1}</{0}>", name, escape(text)));
+}
+
+// escape text for an element's content, characters XML 1.0 can't carry at all, even as
+// references, are replaced with U+FFFD
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
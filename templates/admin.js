@@ -0,0 +1,142 @@
+// script of the /admin page, it's served from its own URL so the page's Content-Security-Policy
+// doesn't have to allow inline scripts
+
+const PAGE_SIZE = 20;
+let offset = 0;
+
+const $ = (id) => document.getElementById(id);
+
+function key() {
+  return sessionStorage.getItem("api_key") || "";
+}
+
+function showStatus(message) {
+  $("status").textContent = message;
+}
+
+// call the API, unwrapping ENVELOPE_RESPONSES envelopes, a failure throws its problem detail
+async function api(method, path, body) {
+  const headers = { "x-api-key": key() };
+  if (body !== undefined) {
+    headers["content-type"] = "application/json";
+  }
+  const response = await fetch(path, {
+    method,
+    headers,
+    body: body === undefined ? undefined : JSON.stringify(body),
+  });
+  const text = await response.text();
+  let data = text;
+  try {
+    data = JSON.parse(text);
+  } catch (e) {}
+  if (data && typeof data === "object" && "success" in data) {
+    data = data.success ? data.data : data.error;
+  }
+  if (!response.ok) {
+    throw new Error((data && data.detail) || response.status + " " + response.statusText);
+  }
+  return data;
+}
+
+async function logIn(candidate) {
+  sessionStorage.setItem("api_key", candidate);
+  try {
+    await api("GET", "/admin/config");
+  } catch (e) {
+    sessionStorage.removeItem("api_key");
+    showStatus("Log in failed: " + e.message);
+    return;
+  }
+  showStatus("");
+  $("login").hidden = true;
+  $("admin").hidden = false;
+  await load();
+}
+
+async function load() {
+  let records;
+  try {
+    records = await api("GET", "/database_read?limit=" + PAGE_SIZE + "&offset=" + offset);
+  } catch (e) {
+    showStatus("Could not read records: " + e.message);
+    return;
+  }
+  $("page").textContent = "records " + (offset + 1) + " to " + (offset + records.length);
+  $("prev").disabled = offset === 0;
+  $("next").disabled = records.length < PAGE_SIZE;
+  const rows = $("records");
+  rows.replaceChildren(...records.map(row));
+}
+
+// a table row with inputs for the date and message, text is only ever set through textContent
+// and value so stored messages can't inject markup
+function row(record) {
+  const tr = document.createElement("tr");
+  const id = document.createElement("td");
+  id.textContent = record.id;
+  const date = document.createElement("input");
+  date.type = "date";
+  date.value = record.date;
+  const message = document.createElement("input");
+  message.value = record.message;
+  message.size = 40;
+
+  const save = document.createElement("button");
+  save.textContent = "Save";
+  save.onclick = () => run(() => api("PATCH", "/database/" + record.id,
+    { date: date.value, message: message.value }));
+  const remove = document.createElement("button");
+  remove.textContent = "Delete";
+  remove.onclick = () => {
+    if (confirm("Delete record " + record.id + "?")) {
+      const query = new URLSearchParams({ id: record.id, date: record.date, message: record.message });
+      run(() => api("DELETE", "/database_delete?" + query));
+    }
+  };
+
+  const cells = [date, message].map((input) => {
+    const td = document.createElement("td");
+    td.append(input);
+    return td;
+  });
+  const actions = document.createElement("td");
+  actions.append(save, " ", remove);
+  tr.append(id, ...cells, actions);
+  return tr;
+}
+
+// run a change and reload the list, reporting a failure
+async function run(change) {
+  try {
+    await change();
+    showStatus("");
+  } catch (e) {
+    showStatus(e.message);
+  }
+  await load();
+}
+
+$("login").onsubmit = (event) => {
+  event.preventDefault();
+  logIn($("key").value);
+};
+$("logout").onclick = () => {
+  sessionStorage.removeItem("api_key");
+  location.reload();
+};
+$("refresh").onclick = load;
+$("prev").onclick = () => { offset = Math.max(0, offset - PAGE_SIZE); load(); };
+$("next").onclick = () => { offset += PAGE_SIZE; load(); };
+$("create").onsubmit = (event) => {
+  event.preventDefault();
+  const form = new FormData(event.target);
+  const record = { id: Number(form.get("id")) };
+  if (form.get("date")) record.date = form.get("date");
+  if (form.get("message")) record.message = form.get("message");
+  run(() => api("POST", "/database_create", record)).then(() => event.target.reset());
+};
+
+if (key()) {
+  logIn(key());
+}